@@ -1,10 +1,37 @@
 #[macro_use]
 extern crate tracing;
 
-use anyhow::Result;
-use botris::{Command, Game, GameState, Piece};
+use anyhow::{Context, Result};
+use bluefin::{Bluefin, Book, EvalWeights, Sampling};
+use botris::{Bot, Game, GameState};
+use clap::Parser;
+use std::path::PathBuf;
 use std::time::{Duration, Instant};
 
+/// Watch bluefin play a game on its own.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Load evaluation weights from a JSON or TOML file.
+    #[arg(long)]
+    weights: Option<PathBuf>,
+    /// Print the line of play the bot expects and its evaluation after every move.
+    #[arg(long)]
+    explain: bool,
+    /// Look past the end of the queue by sampling this many sequences of pieces.
+    #[arg(long)]
+    samples: Option<usize>,
+    /// Number of pieces in each sampled sequence.
+    #[arg(long, default_value_t = 3)]
+    sample_depth: usize,
+    /// Take perfect clears up to this many rows tall when possible.
+    #[arg(long)]
+    pc_height: Option<i8>,
+    /// Load a book of openers to build at the start of the game from a JSON or TOML file.
+    #[arg(long)]
+    openers: Option<PathBuf>,
+}
+
 fn main() -> Result<()> {
     tracing_subscriber::fmt()
         .with_env_filter("bluefin=info")
@@ -13,10 +40,33 @@ fn main() -> Result<()> {
         .compact()
         .init();
 
+    let args = Args::parse();
+    let weights = match &args.weights {
+        Some(path) => EvalWeights::load(path)
+            .with_context(|| format!("failed to load weights from {}", path.display()))?,
+        None => EvalWeights::default(),
+    };
+
     const PPS: f64 = 2.5;
     let delta = Duration::from_secs_f64(1.0 / PPS);
 
     let mut game = Game::new();
+    let mut bot = Bluefin::new().with_weights(weights);
+    if let Some(samples) = args.samples {
+        bot = bot.with_sampling(Sampling {
+            samples,
+            depth: args.sample_depth,
+        });
+    }
+    if let Some(max_height) = args.pc_height {
+        bot = bot.with_perfect_clear(max_height);
+    }
+    if let Some(path) = &args.openers {
+        let book = Book::load(path)
+            .with_context(|| format!("failed to load openers from {}", path.display()))?;
+        bot = bot.with_book(book);
+    }
+    bot.set_pps(PPS as f32);
 
     loop {
         if game.dead {
@@ -26,10 +76,10 @@ fn main() -> Result<()> {
 
         let time = Instant::now();
 
-        match request_move(&game) {
+        match bot.request_move(&game) {
             Some(cmds) => {
-                // TODO: list of events
-                game.perform_commands(&cmds)
+                let outcome = game.perform_commands(&cmds);
+                debug!("{outcome:?}");
             }
             None => {
                 error!("bot gave up!");
@@ -38,6 +88,19 @@ fn main() -> Result<()> {
         }
 
         info!("move calculated in {:.3}s", time.elapsed().as_secs_f64());
+        if let Some(stats) = bot.stats() {
+            debug!(
+                nodes = stats.nodes_expanded,
+                depth = stats.depth,
+                memory = stats.memory,
+                best = stats.best_score,
+            );
+        }
+        if args.explain {
+            if let Some(explanation) = bot.explain() {
+                println!("{explanation}");
+            }
+        }
         print_game_state(&game);
 
         if let Some(wait) = delta.checked_sub(time.elapsed()) {
@@ -79,53 +142,3 @@ fn print_game_state(game: &GameState) {
     println!("+----------+");
     println!();
 }
-
-fn request_move(game: &GameState) -> Option<Vec<Command>> {
-    fn mino_piece_type(pc: Piece) -> mino::standard_rules::Piece {
-        match pc {
-            Piece::I => mino::standard_rules::I,
-            Piece::J => mino::standard_rules::J,
-            Piece::L => mino::standard_rules::L,
-            Piece::O => mino::standard_rules::O,
-            Piece::S => mino::standard_rules::S,
-            Piece::T => mino::standard_rules::T,
-            Piece::Z => mino::standard_rules::Z,
-        }
-    }
-
-    fn botris_command(inp: mino::input::Input) -> Command {
-        match inp {
-            mino::Input::Left => Command::MoveLeft,
-            mino::Input::Right => Command::MoveRight,
-            mino::Input::Cw => Command::RotateCw,
-            mino::Input::Ccw => Command::RotateCcw,
-            mino::Input::SonicDrop => Command::SonicDrop,
-        }
-    }
-
-    let current = mino_piece_type(game.current.piece);
-    let hold = game.held.map(mino_piece_type);
-    let queue = game
-        .queue
-        .iter()
-        .map(|&x| mino_piece_type(x))
-        .collect::<Vec<_>>();
-
-    let mut matrix = mino::MatBuf::new();
-    for (y, row) in game.board.rows().iter().enumerate() {
-        for (x, cell) in row.iter().enumerate() {
-            if cell.is_some() {
-                matrix.set(y as i8, 1u16 << x);
-            }
-        }
-    }
-
-    bluefin::bot(current, &queue, hold, &matrix).map(|(hold, inputs)| {
-        let mut cmds = Vec::with_capacity(inputs.len() + 1);
-        if hold {
-            cmds.push(Command::Hold);
-        }
-        cmds.extend(inputs.iter().map(|&i| botris_command(i)));
-        cmds
-    })
-}
@@ -0,0 +1,127 @@
+//! Measuring how well a set of weights plays, by simulating seeded games.
+
+use bluefin::{Bluefin, EvalWeights, Limits};
+use botris::versus::{Versus, VersusRules};
+use botris::{Bot, Game};
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+
+/// How games are simulated, and how their results are combined into a single score.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub limits: Limits,
+    pub threads: usize,
+    /// Number of moves in each solo game.
+    pub max_pieces: u32,
+    /// Average number of garbage lines received per piece in solo games.
+    pub solo_garbage: f64,
+    pub versus: VersusRules,
+    pub app_weight: f64,
+    pub survival_weight: f64,
+    pub win_weight: f64,
+}
+
+/// Results of a set of games, averaged.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Metrics {
+    /// Attack per piece in solo games.
+    pub app: f64,
+    /// Fraction of the moves of solo games survived.
+    pub survival: f64,
+    /// Fraction of versus rounds won, counting draws as half a win.
+    pub win_rate: f64,
+}
+
+impl Metrics {
+    pub fn fitness(&self, settings: &Settings) -> f64 {
+        settings.app_weight * self.app
+            + settings.survival_weight * self.survival
+            + settings.win_weight * self.win_rate
+    }
+}
+
+fn bot(weights: EvalWeights, settings: &Settings) -> Bluefin {
+    Bluefin::new()
+        .with_limits(settings.limits)
+        .with_threads(settings.threads)
+        .with_weights(weights)
+}
+
+/// Play a solo game with random incoming garbage, returning the attack per piece and
+/// the fraction of moves survived.
+pub fn solo_game(weights: EvalWeights, settings: &Settings, seed: u64) -> (f64, f64) {
+    let mut bot = bot(weights, settings);
+    let mut game = Game::new_seeded(seed).with_garbage_rules(settings.versus.garbage);
+    let mut rng = SmallRng::seed_from_u64(seed);
+
+    // garbage arrives in chunks of 1 to 4 lines, i.e. 2.5 lines on average
+    let p_garbage = (settings.solo_garbage / 2.5).clamp(0.0, 1.0);
+
+    for i in 0..settings.max_pieces {
+        if game.dead {
+            break;
+        }
+        game.set_elapsed(i as f32 / settings.versus.pps);
+        match bot.request_move(&game) {
+            Some(cmds) => game.perform_commands(&cmds),
+            None => break,
+        };
+        if rng.gen_bool(p_garbage) {
+            game.queue_garbage(rng.gen_range(1..=4));
+        }
+    }
+
+    let pieces = game.pieces_placed.max(1) as f64;
+    let app = game.score as f64 / pieces;
+    let survival = game.pieces_placed as f64 / settings.max_pieces as f64;
+    (app, survival)
+}
+
+/// Play solo games with both weight sets and versus rounds between them, once with each
+/// seed. Returns the metrics of `a` and `b`.
+pub fn evaluate(
+    a: EvalWeights,
+    b: EvalWeights,
+    settings: &Settings,
+    seeds: &[u64],
+) -> (Metrics, Metrics) {
+    let mut ma = Metrics::default();
+    let mut mb = Metrics::default();
+    let n = seeds.len() as f64;
+
+    for (i, &seed) in seeds.iter().enumerate() {
+        for (weights, m) in [(a, &mut ma), (b, &mut mb)] {
+            let (app, survival) = solo_game(weights, settings, seed);
+            trace!(seed, app, survival, "solo");
+            m.app += app / n;
+            m.survival += survival / n;
+        }
+
+        // the first player moves first on each turn, so alternate who goes first
+        let (pa, pb) = if i % 2 == 0 {
+            versus_round(a, b, settings, seed)
+        } else {
+            let (pb, pa) = versus_round(b, a, settings, seed);
+            (pa, pb)
+        };
+        ma.win_rate += pa / n;
+        mb.win_rate += pb / n;
+    }
+
+    (ma, mb)
+}
+
+// returns the score of each player: 1 for a win, 0.5 for a draw
+fn versus_round(a: EvalWeights, b: EvalWeights, settings: &Settings, seed: u64) -> (f64, f64) {
+    let mut vs = Versus::new(2, settings.versus, seed);
+    let mut a = bot(a, settings);
+    let mut b = bot(b, settings);
+    let round = vs.play_round(&mut [&mut a, &mut b]);
+    trace!(seed, winner = ?round.winner, moves = round.moves, "versus");
+    match round.winner {
+        Some(0) => (1.0, 0.0),
+        Some(_) => (0.0, 1.0),
+        None => (0.5, 0.5),
+    }
+}
@@ -0,0 +1,237 @@
+#[macro_use]
+extern crate tracing;
+
+mod fitness;
+mod params;
+mod spsa;
+
+use anyhow::{Context, Result};
+use bluefin::{EvalWeights, Limits};
+use botris::versus::VersusRules;
+use botris::GarbageRules;
+use clap::Parser;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use serde_json::json;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use fitness::Settings;
+use params::Params;
+use spsa::{Gains, Spsa};
+
+/// Tune bluefin's evaluation weights by playing seeded games offline.
+///
+/// Each iteration plays solo games and versus rounds with two perturbed weight sets,
+/// and moves the weights in the direction of the better one (SPSA). Every few
+/// iterations the current weights are validated against the initial weights on a fixed
+/// set of games, and the best validated weights are written to the output file.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Number of iterations.
+    #[arg(long, default_value_t = 100)]
+    iterations: u32,
+    /// Seed for all randomness; the same seed and settings give the same results.
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// Weights to start from (JSON or TOML). Defaults to the built-in weights.
+    #[arg(long)]
+    initial: Option<PathBuf>,
+    /// Where to write the best weights (JSON or TOML, by extension).
+    #[arg(long, default_value = "tuned-weights.toml")]
+    output: PathBuf,
+    /// Where to write the progress log, as one JSON object per line.
+    #[arg(long, default_value = "tune-log.jsonl")]
+    log: PathBuf,
+    /// Number of solo games and versus rounds per iteration.
+    #[arg(long, default_value_t = 4)]
+    games: u32,
+    /// Number of solo games and versus rounds used for validation.
+    #[arg(long, default_value_t = 8)]
+    validation_games: u32,
+    /// Validate the current weights every this many iterations.
+    #[arg(long, default_value_t = 5)]
+    validate_every: u32,
+    /// Number of moves in each solo game, and the limit of each versus round.
+    #[arg(long, default_value_t = 300)]
+    max_pieces: u32,
+    /// Average number of garbage lines received per piece in solo games.
+    #[arg(long, default_value_t = 0.4)]
+    solo_garbage: f64,
+    /// Simulated pieces per second, which determines how fast garbage gets messier.
+    #[arg(long, default_value_t = 2.5)]
+    pps: f32,
+    /// Garbage messiness at the start of each game.
+    #[arg(long, default_value_t = 0.05)]
+    initial_messiness: f32,
+    /// Garbage messiness at the end of each game.
+    #[arg(long, default_value_t = 0.2)]
+    final_messiness: f32,
+    /// Number of search generations per move.
+    #[arg(long, default_value_t = 1)]
+    generations: u32,
+    /// Limit each search to this many nodes.
+    #[arg(long)]
+    max_nodes: Option<usize>,
+    /// Number of threads used by each search.
+    #[arg(long, default_value_t = 1)]
+    threads: usize,
+    /// SPSA step size of the first iteration, relative to each weight's magnitude.
+    #[arg(long, default_value_t = 0.05)]
+    step_size: f64,
+    /// SPSA perturbation of the first iteration, relative to each weight's magnitude.
+    #[arg(long, default_value_t = 0.1)]
+    perturbation: f64,
+    /// Importance of attack per piece in the fitness.
+    #[arg(long, default_value_t = 1.0)]
+    app_weight: f64,
+    /// Importance of survival in solo games in the fitness.
+    #[arg(long, default_value_t = 1.0)]
+    survival_weight: f64,
+    /// Importance of the versus win rate in the fitness.
+    #[arg(long, default_value_t = 1.0)]
+    win_weight: f64,
+}
+
+fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter("bluefin_tune=info,bluefin=warn,botris=warn")
+        .with_ansi(true)
+        .with_timer(tracing_subscriber::fmt::time::uptime())
+        .compact()
+        .init();
+
+    let args = Args::parse();
+
+    let initial = match &args.initial {
+        Some(path) => EvalWeights::load(path)
+            .with_context(|| format!("failed to load weights from {}", path.display()))?,
+        None => EvalWeights::default(),
+    };
+    let settings = Settings {
+        limits: Limits {
+            deadline: None,
+            max_nodes: args.max_nodes,
+            max_generations: Some(args.generations),
+        },
+        threads: args.threads,
+        max_pieces: args.max_pieces,
+        solo_garbage: args.solo_garbage,
+        versus: VersusRules {
+            ft: 1,
+            pps: args.pps,
+            garbage: GarbageRules {
+                initial_messiness: args.initial_messiness,
+                final_messiness: args.final_messiness,
+                start_margin: 0.0,
+                end_margin: args.max_pieces as f32 / args.pps,
+                ..GarbageRules::default()
+            },
+            max_pieces: args.max_pieces,
+            ..VersusRules::default()
+        },
+        app_weight: args.app_weight,
+        survival_weight: args.survival_weight,
+        win_weight: args.win_weight,
+    };
+
+    let params = Params::new(&initial)?;
+    info!(
+        "tuning {} weights: {}",
+        params.len(),
+        params.names().join(", ")
+    );
+    let gains = Gains {
+        a: args.step_size,
+        c: args.perturbation,
+        big_a: args.iterations as f64 / 10.0,
+    };
+    let mut spsa = Spsa::new(params.to_vec(&initial)?, gains);
+
+    let mut log = BufWriter::new(
+        File::create(&args.log)
+            .with_context(|| format!("failed to create {}", args.log.display()))?,
+    );
+    let mut rng = SmallRng::seed_from_u64(args.seed);
+    let validation_seeds = (0..args.validation_games)
+        .map(|_| rng.gen())
+        .collect::<Vec<u64>>();
+
+    let validate = |weights: EvalWeights| {
+        let (metrics, _) = fitness::evaluate(weights, initial, &settings, &validation_seeds);
+        (metrics, metrics.fitness(&settings))
+    };
+
+    let (metrics, mut best_fitness) = validate(initial);
+    info!(fitness = best_fitness, ?metrics, "initial weights");
+    log_line(
+        &mut log,
+        json!({
+            "iteration": 0,
+            "validation": metrics,
+            "fitness": best_fitness,
+            "best": true,
+            "weights": initial,
+        }),
+    )?;
+    initial.save(&args.output)?;
+
+    for _ in 0..args.iterations {
+        let seeds = (0..args.games).map(|_| rng.gen()).collect::<Vec<u64>>();
+        let p = spsa.perturb(&mut rng);
+        let plus = params.to_weights(&p.plus)?;
+        let minus = params.to_weights(&p.minus)?;
+        let (m_plus, m_minus) = fitness::evaluate(plus, minus, &settings, &seeds);
+        let (f_plus, f_minus) = (m_plus.fitness(&settings), m_minus.fitness(&settings));
+        spsa.step(&p, f_plus, f_minus);
+
+        let k = spsa.iteration();
+        let weights = params.to_weights(spsa.theta())?;
+        info!(k, f_plus, f_minus, "iteration");
+        log_line(
+            &mut log,
+            json!({
+                "iteration": k,
+                "plus": m_plus,
+                "minus": m_minus,
+                "fitness_plus": f_plus,
+                "fitness_minus": f_minus,
+                "weights": weights,
+            }),
+        )?;
+
+        if k.is_multiple_of(args.validate_every) || k == args.iterations {
+            let (metrics, fitness) = validate(weights);
+            let best = fitness > best_fitness;
+            info!(k, fitness, ?metrics, best, "validation");
+            log_line(
+                &mut log,
+                json!({
+                    "iteration": k,
+                    "validation": metrics,
+                    "fitness": fitness,
+                    "best": best,
+                    "weights": weights,
+                }),
+            )?;
+            if best {
+                best_fitness = fitness;
+                weights.save(&args.output)?;
+            }
+        }
+    }
+
+    println!(
+        "best fitness {best_fitness:.4}, weights written to {}",
+        args.output.display()
+    );
+    Ok(())
+}
+
+fn log_line(log: &mut impl Write, value: serde_json::Value) -> Result<()> {
+    writeln!(log, "{value}")?;
+    log.flush()?;
+    Ok(())
+}
@@ -0,0 +1,97 @@
+//! Conversion between [`EvalWeights`] and the parameter vectors used by the optimizer.
+
+use anyhow::{Context, Result};
+use bluefin::EvalWeights;
+use serde_json::{Map, Value};
+
+/// Weights that are never tuned: `base` only offsets every score, `quad` is the reference
+/// point the other weights are relative to, and `dead` just needs to be very negative.
+const FIXED: [&str; 3] = ["base", "quad", "dead"];
+
+/// Smallest perturbation scale, so that weights that start at zero can still move.
+const MIN_SCALE: f64 = 10.0;
+
+/// The names of the tuned weights and their scales. The optimizer works on weights
+/// divided by their scale, so that it treats large and small weights alike.
+pub struct Params {
+    base: Map<String, Value>,
+    names: Vec<String>,
+    scales: Vec<f64>,
+}
+
+impl Params {
+    pub fn new(initial: &EvalWeights) -> Result<Self> {
+        let base = to_map(initial)?;
+        let mut names = Vec::new();
+        let mut scales = Vec::new();
+        for (name, value) in base.iter() {
+            if FIXED.contains(&name.as_str()) {
+                continue;
+            }
+            let value = value.as_f64().context("weight is not a number")?;
+            names.push(name.clone());
+            scales.push(value.abs().max(MIN_SCALE));
+        }
+        Ok(Self {
+            base,
+            names,
+            scales,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Returns the scaled parameter vector of the weights.
+    pub fn to_vec(&self, weights: &EvalWeights) -> Result<Vec<f64>> {
+        let map = to_map(weights)?;
+        self.names
+            .iter()
+            .zip(&self.scales)
+            .map(|(name, scale)| {
+                let value = map[name].as_f64().context("weight is not a number")?;
+                Ok(value / scale)
+            })
+            .collect()
+    }
+
+    /// Returns the weights of a scaled parameter vector, rounding to the nearest integer.
+    pub fn to_weights(&self, params: &[f64]) -> Result<EvalWeights> {
+        let mut map = self.base.clone();
+        for ((name, scale), x) in self.names.iter().zip(&self.scales).zip(params) {
+            let value = (x * scale).round() as i64;
+            map.insert(name.clone(), value.into());
+        }
+        Ok(serde_json::from_value(Value::Object(map))?)
+    }
+}
+
+fn to_map(weights: &EvalWeights) -> Result<Map<String, Value>> {
+    match serde_json::to_value(weights)? {
+        Value::Object(map) => Ok(map),
+        _ => anyhow::bail!("weights are not a map"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let weights = EvalWeights {
+            combo: 0,
+            ..EvalWeights::default()
+        };
+        let params = Params::new(&weights).unwrap();
+        assert!(!params.names().iter().any(|n| FIXED.contains(&n.as_str())));
+        let v = params.to_vec(&weights).unwrap();
+        assert_eq!(v.len(), params.len());
+        assert_eq!(params.to_weights(&v).unwrap(), weights);
+    }
+}
@@ -0,0 +1,118 @@
+//! Simultaneous perturbation stochastic approximation (SPSA), a derivative-free
+//! optimizer that estimates the gradient from just two noisy measurements per step, no
+//! matter how many parameters there are.
+
+use rand::Rng;
+
+/// Standard exponents of the gain sequences, from Spall (1998).
+const ALPHA: f64 = 0.602;
+const GAMMA: f64 = 0.101;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Gains {
+    /// Step size of the first iteration.
+    pub a: f64,
+    /// Perturbation size of the first iteration.
+    pub c: f64,
+    /// Stability constant, delaying the decay of the step size.
+    pub big_a: f64,
+}
+
+impl Gains {
+    pub fn step_size(&self, k: u32) -> f64 {
+        self.a / (k as f64 + 1.0 + self.big_a).powf(ALPHA)
+    }
+
+    pub fn perturbation(&self, k: u32) -> f64 {
+        self.c / (k as f64 + 1.0).powf(GAMMA)
+    }
+}
+
+/// A pair of points to measure for a single iteration.
+pub struct Perturbation {
+    pub plus: Vec<f64>,
+    pub minus: Vec<f64>,
+    delta: Vec<f64>,
+    c_k: f64,
+}
+
+pub struct Spsa {
+    theta: Vec<f64>,
+    gains: Gains,
+    k: u32,
+}
+
+impl Spsa {
+    pub fn new(theta: Vec<f64>, gains: Gains) -> Self {
+        Self { theta, gains, k: 0 }
+    }
+
+    pub fn theta(&self) -> &[f64] {
+        &self.theta
+    }
+
+    pub fn iteration(&self) -> u32 {
+        self.k
+    }
+
+    /// Perturb every parameter by ±c_k at random.
+    pub fn perturb(&self, rng: &mut impl Rng) -> Perturbation {
+        let c_k = self.gains.perturbation(self.k);
+        let delta = (0..self.theta.len())
+            .map(|_| if rng.gen() { 1.0 } else { -1.0 })
+            .collect::<Vec<f64>>();
+        let plus = self.theta.iter().zip(&delta).map(|(t, d)| t + c_k * d);
+        let minus = self.theta.iter().zip(&delta).map(|(t, d)| t - c_k * d);
+        Perturbation {
+            plus: plus.collect(),
+            minus: minus.collect(),
+            delta,
+            c_k,
+        }
+    }
+
+    /// Move towards the point with the higher fitness, given the fitness measured at both
+    /// points of the perturbation.
+    pub fn step(&mut self, p: &Perturbation, fitness_plus: f64, fitness_minus: f64) {
+        let a_k = self.gains.step_size(self.k);
+        let diff = fitness_plus - fitness_minus;
+        for (t, d) in self.theta.iter_mut().zip(&p.delta) {
+            // gradient estimate is diff / (2 c_k d), and 1 / d = d
+            *t += a_k * diff * d / (2.0 * p.c_k);
+        }
+        self.k += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::rngs::SmallRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_maximize_quadratic() {
+        let target = [1.0, -2.0, 0.5];
+        let f = |x: &[f64]| {
+            -x.iter()
+                .zip(&target)
+                .map(|(x, t)| (x - t).powi(2))
+                .sum::<f64>()
+        };
+        let gains = Gains {
+            a: 0.5,
+            c: 0.1,
+            big_a: 10.0,
+        };
+        let mut spsa = Spsa::new(vec![0.0; 3], gains);
+        let mut rng = SmallRng::seed_from_u64(0);
+        for _ in 0..500 {
+            let p = spsa.perturb(&mut rng);
+            let (fp, fm) = (f(&p.plus), f(&p.minus));
+            spsa.step(&p, fp, fm);
+        }
+        for (x, t) in spsa.theta().iter().zip(&target) {
+            assert!((x - t).abs() < 0.05, "{:?}", spsa.theta());
+        }
+    }
+}
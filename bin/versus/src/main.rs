@@ -0,0 +1,134 @@
+#[macro_use]
+extern crate tracing;
+
+use anyhow::Result;
+use bluefin::{Bluefin, Limits};
+use botris::versus::{Versus, VersusRules};
+use botris::{GameState, GarbageRules, SpinRules};
+use clap::{Parser, ValueEnum};
+use std::time::Instant;
+
+/// Play bluefin against itself in a local versus match.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Number of round wins needed to win the match.
+    #[arg(long, default_value_t = 7)]
+    ft: u32,
+    /// Simulated pieces per second.
+    #[arg(long, default_value_t = 2.5)]
+    pps: f32,
+    /// Garbage messiness at the start of each round.
+    #[arg(long, default_value_t = 0.05)]
+    initial_messiness: f32,
+    /// Garbage messiness at the end of each round.
+    #[arg(long, default_value_t = 0.2)]
+    final_messiness: f32,
+    /// Rules deciding which placements are spins.
+    #[arg(long, value_enum, default_value_t = SpinArg::AllSpin)]
+    spin: SpinArg,
+    /// Maximum moves per player before a round is called a draw.
+    #[arg(long, default_value_t = 1000)]
+    max_pieces: u32,
+    /// Stop after this many rounds, even if nobody has won the match.
+    #[arg(long, default_value_t = 100)]
+    max_rounds: u32,
+    /// Limit each search to this many nodes, instead of a fixed number of generations.
+    #[arg(long)]
+    max_nodes: Option<usize>,
+    /// Seed for the piece sequences and garbage.
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug)]
+enum SpinArg {
+    AllSpin,
+    TSpin,
+    AllMini,
+}
+
+impl From<SpinArg> for SpinRules {
+    fn from(spin: SpinArg) -> Self {
+        match spin {
+            SpinArg::AllSpin => SpinRules::AllSpin,
+            SpinArg::TSpin => SpinRules::TSpin,
+            SpinArg::AllMini => SpinRules::AllMini,
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter("botris=info,bluefin=warn,bluefin_versus=info")
+        .with_ansi(true)
+        .with_timer(tracing_subscriber::fmt::time::uptime())
+        .compact()
+        .init();
+
+    let args = Args::parse();
+    let rules = VersusRules {
+        ft: args.ft,
+        pps: args.pps,
+        garbage: GarbageRules {
+            initial_messiness: args.initial_messiness,
+            final_messiness: args.final_messiness,
+            start_margin: 0.0,
+            end_margin: 180.0,
+            ..GarbageRules::default()
+        },
+        spin: args.spin.into(),
+        max_pieces: args.max_pieces,
+        max_rounds: args.max_rounds,
+    };
+
+    let mut vs = Versus::new(2, rules, args.seed);
+    let limits = match args.max_nodes {
+        Some(n) => Limits::nodes(n),
+        None => Limits::default(),
+    };
+    let bot = || {
+        Bluefin::new()
+            .with_limits(limits)
+            .with_spin_rules(rules.spin)
+    };
+    let (mut p1, mut p2) = (bot(), bot());
+
+    for _ in 0..args.max_rounds {
+        if vs.winner().is_some() {
+            break;
+        }
+
+        let time = Instant::now();
+        let round = vs.play_round(&mut [&mut p1, &mut p2]);
+        info!(
+            "round over: winner {:?}, {} moves ({:.1}s simulated, {:.1}s real)",
+            round.winner,
+            round.moves,
+            round.duration,
+            time.elapsed().as_secs_f64()
+        );
+        for (i, game) in round.games.iter().enumerate() {
+            print_summary(i, game);
+        }
+        info!("score: {:?}", vs.wins());
+    }
+
+    match vs.winner() {
+        Some(winner) => println!("winner: player {winner}, score {:?}", vs.wins()),
+        None => println!("no winner, score {:?}", vs.wins()),
+    }
+    Ok(())
+}
+
+fn print_summary(i: usize, game: &GameState) {
+    let pcs = game.pieces_placed;
+    let atk = game.score;
+    let eff = if pcs > 0 {
+        atk as f64 / pcs as f64
+    } else {
+        0.0
+    };
+    let dead = if game.dead { " (dead)" } else { "" };
+    println!("player {i}: pcs: {pcs}, atk: {atk}, eff: {eff:.3} app{dead}");
+}
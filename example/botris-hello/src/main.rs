@@ -6,13 +6,15 @@ extern crate tracing;
 use anyhow::{Context, Result};
 use clap::Parser;
 use futures::{SinkExt as _, StreamExt as _};
+use std::path::PathBuf;
 use std::time::{Duration, SystemTime};
 use tokio_tungstenite::connect_async as ws_connect_async;
 use tokio_tungstenite::tungstenite;
 use tungstenite::http::Uri;
 
+use bluefin::{Bluefin, EvalWeights};
 use botris::api::{ClientMessage, Message, UnknownMessage};
-use botris::game::Command;
+use botris::Bot;
 
 /// Botris API example.
 #[derive(Parser, Debug)]
@@ -21,6 +23,12 @@ struct Args {
     #[arg(long)]
     token: String,
     room_key: String,
+    /// Number of threads used by the search.
+    #[arg(long, default_value_t = 1)]
+    threads: usize,
+    /// Load evaluation weights from a JSON or TOML file.
+    #[arg(long)]
+    weights: Option<PathBuf>,
 }
 
 #[tokio::main(flavor = "current_thread")]
@@ -33,8 +41,17 @@ async fn main() -> Result<()> {
         .compact()
         .init();
 
+    let args = Args::parse();
+    let weights = match &args.weights {
+        Some(path) => EvalWeights::load(path)
+            .with_context(|| format!("failed to load weights from {}", path.display()))?,
+        None => EvalWeights::default(),
+    };
+
     let mut ws = {
-        let Args { room_key, token } = Args::parse();
+        let Args {
+            room_key, token, ..
+        } = &args;
 
         let uri = format!("wss://botrisbattle.com/ws?token={token}&roomKey={room_key}");
         let uri: Uri = uri.parse().context("invalid URI")?;
@@ -46,6 +63,9 @@ async fn main() -> Result<()> {
 
     let mut the_session_id = None;
     let mut the_room_data = None;
+    let mut bot = Bluefin::new()
+        .with_threads(args.threads)
+        .with_weights(weights);
 
     let mut round_start_time = SystemTime::UNIX_EPOCH;
     let mut round_request_count = 0;
@@ -89,6 +109,7 @@ async fn main() -> Result<()> {
             | Message::GameReset { room_data } => {
                 info!("room reset");
                 debug!("{room_data:?}");
+                bot.set_pps(room_data.pps);
                 the_room_data = Some(room_data);
             }
 
@@ -116,8 +137,10 @@ async fn main() -> Result<()> {
 
                 round_start_time = t0;
                 round_request_count = 0;
+                bot.new_round();
 
                 let room_data = the_room_data.insert(room_data);
+                bot.set_pps(room_data.pps);
 
                 if let Some(game_state) = room_data
                     .players
@@ -173,55 +196,7 @@ async fn main() -> Result<()> {
                     game_state.current.piece, game_state.held, game_state.queue
                 );
 
-                let result = {
-                    fn mino_piece_type(pc: botris::Piece) -> mino::standard_rules::Piece {
-                        match pc {
-                            botris::Piece::I => mino::standard_rules::I,
-                            botris::Piece::J => mino::standard_rules::J,
-                            botris::Piece::L => mino::standard_rules::L,
-                            botris::Piece::O => mino::standard_rules::O,
-                            botris::Piece::S => mino::standard_rules::S,
-                            botris::Piece::T => mino::standard_rules::T,
-                            botris::Piece::Z => mino::standard_rules::Z,
-                        }
-                    }
-
-                    fn botris_command(inp: mino::input::Input) -> botris::Command {
-                        match inp {
-                            mino::Input::Left => botris::Command::MoveLeft,
-                            mino::Input::Right => botris::Command::MoveRight,
-                            mino::Input::Cw => botris::Command::RotateCw,
-                            mino::Input::Ccw => botris::Command::RotateCcw,
-                            mino::Input::SonicDrop => botris::Command::SonicDrop,
-                        }
-                    }
-
-                    let current = mino_piece_type(game_state.current.piece);
-                    let hold = game_state.held.map(mino_piece_type);
-                    let queue = game_state
-                        .queue
-                        .iter()
-                        .map(|&x| mino_piece_type(x))
-                        .collect::<Vec<_>>();
-
-                    let mut matrix = mino::MatBuf::new();
-                    for (y, row) in game_state.board.rows().iter().enumerate() {
-                        for (x, cell) in row.iter().enumerate() {
-                            if cell.is_some() {
-                                matrix.set(y as i8, 1u16 << x);
-                            }
-                        }
-                    }
-
-                    bluefin::bot(current, &queue, hold, &matrix).map(|(hold, inputs)| {
-                        let mut cmds = Vec::with_capacity(inputs.len() + 1);
-                        if hold {
-                            cmds.push(Command::Hold);
-                        }
-                        cmds.extend(inputs.iter().map(|&i| botris_command(i)));
-                        cmds
-                    })
-                };
+                let result = bot.request_move(&game_state);
 
                 if let Some(commands) = &result {
                     let msg = ClientMessage::Action { commands };
@@ -246,6 +221,8 @@ async fn main() -> Result<()> {
                 if session_id == *the_session_id {
                     info!("game state update via 'player_action'");
                     debug!("{game_state:?}");
+                } else {
+                    bot.opponent_update(&session_id, &game_state);
                 }
             }
 
@@ -263,6 +240,9 @@ async fn main() -> Result<()> {
                 if session_id == *the_session_id {
                     info!("game state update via 'player_damage_received'");
                     debug!("{game_state:?}");
+                    bot.garbage_received(damage, &game_state);
+                } else {
+                    bot.opponent_update(&session_id, &game_state);
                 }
             }
 
@@ -0,0 +1,97 @@
+//! Tracking the 7-bag randomizer from the pieces seen so far, used to sample the pieces
+//! that come after the end of the queue.
+
+use mino::standard_rules::Piece;
+use mino::BagTracker;
+use rand::Rng;
+
+/// Pieces seen so far, in the order they were generated.
+#[derive(Clone, Debug)]
+pub struct History {
+    bag: BagTracker<Piece>,
+    // the pieces given to the last call to `observe`
+    last: Vec<Piece>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            bag: BagTracker::new(&Piece::ALL),
+            last: Vec::new(),
+        }
+    }
+}
+
+impl History {
+    /// Observe the pieces of `upcoming` (the current piece followed by the queue) that
+    /// were not seen before. If `upcoming` does not continue the pieces given last time,
+    /// tracking starts over from `upcoming`, and if a piece could not have been dealt by a
+    /// 7-bag randomizer, it starts over from that piece.
+    pub fn observe(&mut self, upcoming: &[Piece]) {
+        let last = &self.last;
+        // number of pieces that were played since last time
+        let played = (0..last.len()).find(|&n| {
+            let known = &last[n..];
+            upcoming.len() >= known.len() && upcoming.starts_with(known)
+        });
+        let new = match played {
+            Some(n) => &upcoming[last.len() - n..],
+            None => {
+                self.bag = BagTracker::new(&Piece::ALL);
+                upcoming
+            }
+        };
+        for &pc in new {
+            if !self.bag.observe(pc) {
+                debug!("the pieces are not dealt by a 7-bag randomizer");
+                // start over from this piece rather than stop tracking for good
+                self.bag = BagTracker::new(&Piece::ALL);
+                self.bag.observe(pc);
+            }
+        }
+        self.last.clear();
+        self.last.extend_from_slice(upcoming);
+    }
+
+    /// Sample `n` pieces that may follow the pieces seen so far.
+    pub fn sample(&self, rng: &mut impl Rng, n: usize) -> Vec<Piece> {
+        self.bag.sample(rng, n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mino::standard_rules::Piece::*;
+
+    #[test]
+    fn test_observe() {
+        let mut history = History::default();
+        history.observe(&[I, J, L, O, S, T, Z]);
+        assert!(!history.bag.is_known());
+        // only the last Z is new; the two Zs must be in different bags
+        history.observe(&[J, L, O, S, T, Z, Z]);
+        assert!(history.bag.is_known());
+        assert_eq!(history.bag.next_pieces(), [I, J, L, O, S, T]);
+
+        // does not continue the previous pieces
+        history.observe(&[T, T, T]);
+        assert!(history.bag.is_consistent());
+        history.observe(&[S, Z]);
+        assert!(history.bag.is_consistent());
+    }
+
+    #[test]
+    fn test_observe_after_inconsistent() {
+        let mut history = History::default();
+        history.observe(&[I, J, L, O, S, T, Z]);
+        // a second T in the same bag; tracking starts over from it
+        history.observe(&[J, L, O, S, T, Z, T, T]);
+        assert!(history.bag.is_consistent());
+        assert!(!history.bag.is_known());
+        // and keeps observing the pieces that follow
+        history.observe(&[Z, T, T, I, J, L, O, S, Z, Z]);
+        assert!(history.bag.is_known());
+        assert_eq!(history.bag.next_pieces(), [I, J, L, O, S, T]);
+    }
+}
@@ -1,48 +1,83 @@
-use crate::Alloc;
-
 use mino::matrix::{Mat, MatBuf};
 use mino::places::places;
-use mino::standard_rules::{FallingPiece, Queue};
-use std::cell::Cell;
+use mino::standard_rules::{FallingPiece, Piece, Queue};
+use mino::{Spin, SpinRules};
+use std::collections::hash_map::{DefaultHasher, Entry};
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
 
-use crate::eval::evaluate;
+use crate::eval::{evaluate, EvalWeights};
 use crate::state::State;
 
-pub struct Node<'a> {
-    matrix: &'a Mat,
-    queue: Queue<'a>,
+/// Index of a node in a [`Tree`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct NodeId(u32);
+
+impl NodeId {
+    pub(crate) fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Search tree. Nodes are stored in a single vector and refer to each other by
+/// [`NodeId`], which lets the tree be kept around between moves: the subtree of the move
+/// that was actually played becomes the new tree.
+///
+/// Positions that can be reached by placing pieces in a different order are merged
+/// using a transposition table, so strictly speaking this is a DAG rather than a tree.
+/// Each node remembers the first parent it was reached from.
+pub struct Tree {
+    nodes: Vec<Node>,
+    // matrix rows of every node
+    rows: Vec<u16>,
+    // every piece in the queue known so far; nodes refer to suffixes of this
+    pieces: Vec<Piece>,
+    // lists of children of every expanded node, and the moves leading to them
+    children: Vec<NodeId>,
+    moves: Vec<Move>,
+    // hash of (matrix, queue, state) of every node
+    table: HashMap<u64, NodeId>,
+    transpositions: usize,
+    weights: EvalWeights,
+    spin_rules: SpinRules,
+}
+
+pub struct Node {
+    rows: (u32, u32),
+    hold: Option<Piece>,
+    next: u32,
     score: i32,
     state: State,
-    // depth: i32,
-    // score: i32,
-    parent: Option<Edge<'a>>,
-    children: Cell<&'a [&'a Node<'a>]>,
+    parent: Option<(NodeId, Move)>,
+    // `None` if not expanded yet
+    children: Option<(u32, u32)>,
 }
 
-#[derive(Copy, Clone)]
-struct Edge<'a> {
-    node: &'a Node<'a>,
-    piece: FallingPiece,
-    // cleared: u8,
-    // is_spin: bool,
+/// Children of a node, computed by [`Tree::generate`].
+pub struct Expansion {
+    id: NodeId,
+    children: Vec<Child>,
 }
 
-fn copy_matrix<'a>(alo: &'a Alloc, mat: &Mat) -> &'a Mat {
-    Mat::new(alo.alloc_slice_copy(mat.rows()))
+struct Child {
+    matrix: MatBuf,
+    hold: Option<Piece>,
+    next: u32,
+    state: State,
+    score: i32,
+    hash: u64,
+    mv: Move,
 }
 
-impl<'a> Node<'a> {
-    pub fn root(alo: &'a Alloc, matrix: &'a Mat, queue: Queue<'a>, b2b: bool) -> &'a Self {
-        alo.alloc_with(|| Node {
-            matrix,
-            queue,
-            state: State::new(b2b),
-            score: i32::MIN,
-            parent: None,
-            children: Cell::new(&[]),
-        })
-    }
+/// Placement leading from a node to one of its children.
+#[derive(Copy, Clone, Debug)]
+pub struct Move {
+    pub piece: FallingPiece,
+    pub cleared: u8,
+    pub spin: Spin,
+}
 
+impl Node {
     pub fn score(&self) -> i32 {
         self.score
     }
@@ -51,6 +86,10 @@ impl<'a> Node<'a> {
         self.state
     }
 
+    pub fn is_expanded(&self) -> bool {
+        self.children.is_some()
+    }
+
     // if `n.ord_max_best() < m.ord_max_best()` then `n` is better than `m`
     pub fn ord_min_best(&self) -> std::cmp::Reverse<i32> {
         std::cmp::Reverse(self.ord_max_best())
@@ -60,56 +99,532 @@ impl<'a> Node<'a> {
     pub fn ord_max_best(&self) -> i32 {
         self.score()
     }
+}
+
+fn hash_position(rows: &[u16], hold: Option<Piece>, next: u32, state: State) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    rows.hash(&mut hasher);
+    hold.hash(&mut hasher);
+    next.hash(&mut hasher);
+    state.hash(&mut hasher);
+    hasher.finish()
+}
+
+// calls `f` with every position that can be reached by placing the next piece (or the
+// hold piece), along with the queue and state afterwards and the move that was made
+fn placements<'a>(
+    matrix: &Mat,
+    queue: Queue<'a>,
+    state: State,
+    spin_rules: SpinRules,
+    mut f: impl FnMut(&MatBuf, Queue<'a>, State, Move),
+) {
+    let mut new_matrix = MatBuf::new();
+    for (pc, queue) in queue.pop() {
+        for pl in places(matrix, pc) {
+            new_matrix.copy_from(matrix);
+            new_matrix.place(pl.cells);
+            let cleared = new_matrix.clear_lines(pl.cells.bottom());
+            let spin = match cleared {
+                0 => Spin::None,
+                _ => spin_rules.detect(matrix, pl.falling_piece),
+            };
+            let state = state.next(cleared, spin).check_top_out(new_matrix.len());
+            let mv = Move {
+                piece: pl.falling_piece,
+                cleared,
+                spin,
+            };
+            f(&new_matrix, queue, state, mv);
+        }
+    }
+}
+
+impl Tree {
+    /// Create a new tree containing just the root node, with queue `pieces` (the first of
+    /// which is the hold piece, or the current piece if the hold is empty).
+    pub fn new(matrix: &Mat, pieces: &[Piece], state: State) -> Self {
+        let mut tree = Tree {
+            nodes: Vec::with_capacity(1024),
+            rows: Vec::with_capacity(16 * 1024),
+            pieces: pieces.to_vec(),
+            children: Vec::with_capacity(1024),
+            moves: Vec::with_capacity(1024),
+            table: HashMap::with_capacity(1024),
+            transpositions: 0,
+            weights: EvalWeights::default(),
+            spin_rules: SpinRules::default(),
+        };
+        let queue = Queue::from(pieces);
+        let next = (pieces.len() - queue.next().len()) as u32;
+        tree.push_node(matrix, queue.hold(), next, state, i32::MIN, None);
+        tree
+    }
+
+    /// Set the weights used to evaluate new nodes. This should be done before the root
+    /// is expanded.
+    pub fn with_weights(mut self, weights: EvalWeights) -> Self {
+        self.weights = weights;
+        self
+    }
+
+    pub fn weights(&self) -> &EvalWeights {
+        &self.weights
+    }
+
+    /// Set the rules deciding which placements are spins. This should be done before the
+    /// root is expanded.
+    pub fn with_spin_rules(mut self, spin_rules: SpinRules) -> Self {
+        self.spin_rules = spin_rules;
+        self
+    }
+
+    pub fn spin_rules(&self) -> SpinRules {
+        self.spin_rules
+    }
+
+    pub fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Approximate number of bytes allocated by the tree.
+    pub fn memory_usage(&self) -> usize {
+        use std::mem::size_of;
+        self.nodes.capacity() * size_of::<Node>()
+            + self.rows.capacity() * size_of::<u16>()
+            + self.pieces.capacity() * size_of::<Piece>()
+            + self.children.capacity() * size_of::<NodeId>()
+            + self.moves.capacity() * size_of::<Move>()
+            // the table also has a control byte per entry
+            + self.table.capacity() * (size_of::<(u64, NodeId)>() + 1)
+    }
+
+    /// Number of times a child was found in the transposition table instead of being
+    /// added as a new node, since the tree was created.
+    pub fn transpositions(&self) -> usize {
+        self.transpositions
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.index()]
+    }
+
+    pub fn matrix(&self, id: NodeId) -> &Mat {
+        let (start, end) = self.node(id).rows;
+        Mat::new(&self.rows[start as usize..end as usize])
+    }
+
+    pub fn queue(&self, id: NodeId) -> Queue<'_> {
+        let node = self.node(id);
+        Queue::new(node.hold, &self.pieces[node.next as usize..])
+    }
+
+    /// Returns the children of the node, or an empty slice if it has not been expanded.
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        match self.node(id).children {
+            Some((start, end)) => &self.children[start as usize..end as usize],
+            None => &[],
+        }
+    }
+
+    /// Returns the moves leading to each of [`Tree::children`].
+    pub fn moves(&self, id: NodeId) -> &[Move] {
+        match self.node(id).children {
+            Some((start, end)) => &self.moves[start as usize..end as usize],
+            None => &[],
+        }
+    }
 
-    pub fn original_piece(&self) -> Option<FallingPiece> {
-        let mut edge = self.parent;
+    pub fn original_piece(&self, id: NodeId) -> Option<FallingPiece> {
+        let mut parent = self.node(id).parent;
         let mut target = None;
-        while let Some(e) = edge {
-            target = Some(e.piece);
-            edge = e.node.parent;
+        while let Some((node, mv)) = parent {
+            target = Some(mv.piece);
+            parent = self.node(node).parent;
         }
         target
     }
 
-    pub fn children(&self) -> &'a [&'a Node<'a>] {
-        self.children.get()
+    /// Returns the nodes on the path from the root to the node, following the first
+    /// parent of each node. The root itself is not included.
+    pub fn path(&self, id: NodeId) -> Vec<NodeId> {
+        let mut path = vec![id];
+        let mut parent = self.node(id).parent;
+        while let Some((node, _)) = parent {
+            path.push(node);
+            parent = self.node(node).parent;
+        }
+        path.pop();
+        path.reverse();
+        path
     }
 
-    pub fn expand(&'a self, alo: &'a Alloc) -> &'a [&'a Node<'a>] {
-        let mut children = Vec::with_capacity(64);
-        let mut new_matrix = MatBuf::new();
-
-        for (pc, queue) in self.queue.pop() {
-            for pl in places(self.matrix, pc) {
-                new_matrix.copy_from(self.matrix);
-                let is_spin = pl.cells.immobile(&new_matrix);
-                new_matrix.place(pl.cells);
-                let cleared = new_matrix.clear_lines(pl.cells.bottom());
-                let state = self.state.next(cleared, is_spin);
+    fn push_node(
+        &mut self,
+        matrix: &Mat,
+        hold: Option<Piece>,
+        next: u32,
+        state: State,
+        score: i32,
+        parent: Option<(NodeId, Move)>,
+    ) -> NodeId {
+        let id = NodeId(self.nodes.len() as u32);
+        let start = self.rows.len() as u32;
+        self.rows.extend_from_slice(matrix.rows());
+        self.nodes.push(Node {
+            rows: (start, self.rows.len() as u32),
+            hold,
+            next,
+            score,
+            state,
+            parent,
+            children: None,
+        });
+        id
+    }
 
-                // TODO: check transposition table
+    // returns the node with the same position, if there is one
+    fn lookup(
+        &self,
+        hash: u64,
+        matrix: &Mat,
+        hold: Option<Piece>,
+        next: u32,
+        state: State,
+    ) -> Option<NodeId> {
+        let id = *self.table.get(&hash)?;
+        let node = self.node(id);
+        let same = self.matrix(id).rows() == matrix.rows()
+            && node.hold == hold
+            && node.next == next
+            && node.state == state;
+        // on a hash collision the position is simply not merged
+        same.then_some(id)
+    }
 
-                let matrix = copy_matrix(alo, &new_matrix);
-                let score = evaluate(matrix, state);
+    /// Compute the children of the node without modifying the tree, so that several
+    /// nodes can be expanded in parallel. The children are added by [`Tree::insert`].
+    pub fn generate(&self, id: NodeId) -> Expansion {
+        let parent = self.node(id);
+        let mut children = Vec::new();
+        if parent.state.is_dead {
+            return Expansion { id, children };
+        }
 
-                children.push(alo.alloc_with(move || Node {
-                    matrix,
-                    queue,
-                    score,
+        children.reserve(64);
+        let matrix = self.matrix(id);
+        placements(
+            matrix,
+            self.queue(id),
+            parent.state,
+            self.spin_rules,
+            |new_matrix, queue, state, mv| {
+                let hold = queue.hold();
+                let next = (self.pieces.len() - queue.next().len()) as u32;
+                children.push(Child {
+                    hash: hash_position(new_matrix.rows(), hold, next, state),
+                    score: evaluate(&self.weights, new_matrix, state),
+                    matrix: new_matrix.clone(),
+                    hold,
+                    next,
                     state,
-                    parent: Some(Edge {
-                        node: self,
-                        piece: pl.falling_piece,
-                        // cleared,
-                        // is_spin,
-                    }),
-                    children: Cell::new(&[]),
-                }) as &Node);
+                    mv,
+                });
+            },
+        );
+
+        Expansion { id, children }
+    }
+
+    /// Estimate the score of the node if play continued after the end of the queue with
+    /// `pieces`, by placing each piece where it gives the best score.
+    pub fn rollout(&self, id: NodeId, pieces: &[Piece]) -> i32 {
+        let node = self.node(id);
+        let known = &self.pieces[node.next as usize..];
+        let pieces = [known, pieces].concat();
+        let mut queue = Queue::new(node.hold, &pieces);
+        let mut matrix = MatBuf::new();
+        matrix.copy_from(self.matrix(id));
+        let mut state = node.state;
+        let mut score = node.score;
+
+        while !queue.next().is_empty() && !state.is_dead {
+            let mut best: Option<(i32, MatBuf, Queue, State)> = None;
+            placements(
+                &matrix,
+                queue,
+                state,
+                self.spin_rules,
+                |new_matrix, new_queue, new_state, _| {
+                    let new_score = evaluate(&self.weights, new_matrix, new_state);
+                    if best.as_ref().is_none_or(|&(s, ..)| new_score > s) {
+                        best = Some((new_score, new_matrix.clone(), new_queue, new_state));
+                    }
+                },
+            );
+            let Some((new_score, new_matrix, new_queue, new_state)) = best else {
+                // the spawn position is blocked
+                return self.weights.dead;
+            };
+            (score, matrix, queue, state) = (new_score, new_matrix, new_queue, new_state);
+        }
+        score
+    }
+
+    /// Add the children computed by [`Tree::generate`] to the tree. Returns the number of
+    /// new nodes, which does not include children that were already in the tree.
+    pub fn insert(&mut self, expansion: Expansion) -> usize {
+        let Expansion { id, children } = expansion;
+        let start = self.children.len() as u32;
+        let first_new = self.nodes.len() as u32;
+        let mut n_new = 0;
+        for ch in children {
+            let child = match self.lookup(ch.hash, &ch.matrix, ch.hold, ch.next, ch.state) {
+                // another placement of this node already led to the same position
+                Some(child) if child.0 >= first_new => continue,
+                Some(child) if self.children[start as usize..].contains(&child) => continue,
+                Some(child) => {
+                    self.transpositions += 1;
+                    child
+                }
+                None => {
+                    let parent = Some((id, ch.mv));
+                    let child =
+                        self.push_node(&ch.matrix, ch.hold, ch.next, ch.state, ch.score, parent);
+                    self.table.entry(ch.hash).or_insert(child);
+                    n_new += 1;
+                    child
+                }
+            };
+            self.children.push(child);
+            self.moves.push(ch.mv);
+        }
+        let end = self.children.len() as u32;
+        self.nodes[id.index()].children = Some((start, end));
+        n_new
+    }
+
+    /// Add newly revealed pieces to the end of the queue. Nodes that were expanded when
+    /// there were no next pieces left have to be expanded again, since they could only
+    /// place their hold piece.
+    pub fn extend_queue(&mut self, pieces: &[Piece]) {
+        if pieces.is_empty() {
+            return;
+        }
+        let end = self.pieces.len() as u32;
+        for node in self.nodes.iter_mut() {
+            if node.next == end {
+                node.children = None;
+            }
+        }
+        self.pieces.extend_from_slice(pieces);
+    }
+
+    /// Discard everything except for the nodes reachable from `id`, which becomes the new
+    /// root with the given state. The states and scores of the rest of the nodes are
+    /// recomputed, since they depend on the state of the root.
+    pub fn reroot(&mut self, id: NodeId, state: State) {
+        let pieces = std::mem::take(&mut self.pieces);
+        let new = Tree {
+            nodes: Vec::with_capacity(self.nodes.capacity()),
+            rows: Vec::with_capacity(self.rows.capacity()),
+            pieces,
+            children: Vec::with_capacity(self.children.capacity()),
+            moves: Vec::with_capacity(self.moves.capacity()),
+            table: HashMap::with_capacity(self.table.capacity()),
+            transpositions: 0,
+            weights: self.weights,
+            spin_rules: self.spin_rules,
+        };
+        let old = std::mem::replace(self, new);
+
+        // breadth first, so that each node is added after the parent it was first
+        // reached from, which is needed to compute its state
+        let mut new_ids = HashMap::with_capacity(old.nodes.len());
+        let mut queue = VecDeque::from([id]);
+        let root = old.node(id);
+        self.push_node(old.matrix(id), root.hold, root.next, state, i32::MIN, None);
+        new_ids.insert(id, self.root());
+
+        while let Some(old_id) = queue.pop_front() {
+            let new_id = new_ids[&old_id];
+            let node = old.node(old_id);
+            let node_state = self.node(new_id).state;
+
+            // nodes whose children are still valid keep them; nodes that died or came
+            // back to life have to be expanded again
+            let children = match node.children {
+                Some(_) if node_state.is_dead => Some((0, 0)),
+                Some(_) if node.state.is_dead => None,
+                Some((start, end)) => {
+                    let new_start = self.children.len() as u32;
+                    for i in start as usize..end as usize {
+                        let (child, mv) = (old.children[i], old.moves[i]);
+                        let new_child = match new_ids.entry(child) {
+                            Entry::Occupied(e) => *e.get(),
+                            Entry::Vacant(e) => {
+                                let c = old.node(child);
+                                let matrix = old.matrix(child);
+                                let state = node_state
+                                    .next(mv.cleared, mv.spin)
+                                    .check_top_out(matrix.len());
+                                let score = evaluate(&self.weights, matrix, state);
+                                let parent = Some((new_id, mv));
+                                queue.push_back(child);
+                                *e.insert(
+                                    self.push_node(matrix, c.hold, c.next, state, score, parent),
+                                )
+                            }
+                        };
+                        self.children.push(new_child);
+                        self.moves.push(mv);
+                    }
+                    Some((new_start, self.children.len() as u32))
+                }
+                None => None,
+            };
+            self.nodes[new_id.index()].children = children;
+        }
+
+        for i in 0..self.nodes.len() {
+            let id = NodeId(i as u32);
+            let node = self.node(id);
+            let hash = hash_position(self.matrix(id).rows(), node.hold, node.next, node.state);
+            self.table.entry(hash).or_insert(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mino::standard_rules::Piece::*;
+    use std::collections::HashSet;
+
+    // number of distinct matrices after placing `pc` on an empty matrix, e.g. not
+    // counting both vertical orientations of an I piece at the same location
+    fn n_positions(pc: Piece) -> usize {
+        let mut matrices = HashSet::new();
+        for pl in places(Mat::empty(), pc) {
+            let mut matrix = MatBuf::new();
+            matrix.place(pl.cells);
+            matrices.insert(matrix.rows().to_vec());
+        }
+        matrices.len()
+    }
+
+    #[test]
+    fn test_expand() {
+        let mut tree = Tree::new(Mat::empty(), &[T, I], State::default());
+        let root = tree.root();
+        assert!(!tree.node(root).is_expanded());
+        let n = tree.insert(tree.generate(root));
+        assert_eq!(n, n_positions(T) + n_positions(I));
+        assert_eq!(tree.children(root).len(), n);
+        for &child in tree.children(root) {
+            assert_eq!(tree.queue(child).next().len(), 0);
+            assert!(tree.original_piece(child).is_some());
+        }
+    }
+
+    #[test]
+    fn test_reroot() {
+        let mut tree = Tree::new(Mat::empty(), &[T, I, O], State::default());
+        let root = tree.root();
+        tree.insert(tree.generate(root));
+        let child = tree.children(root)[0];
+        let n = tree.insert(tree.generate(child));
+        let grandchild = tree.children(child)[0];
+        let matrix = tree.matrix(grandchild).rows().to_vec();
+
+        let state = State::new(true, 0, 0);
+        tree.reroot(child, state);
+        let root = tree.root();
+        assert_eq!(tree.len(), 1 + n);
+        assert_eq!(tree.node(root).state(), state);
+        assert!(tree.original_piece(root).is_none());
+        let grandchild = tree.children(root)[0];
+        assert_eq!(tree.matrix(grandchild).rows(), matrix);
+        assert_eq!(tree.original_piece(grandchild).map(|fp| fp.piece), Some(I));
+        // the states of descendants are computed from the new root state
+        assert!(tree
+            .children(root)
+            .iter()
+            .all(|&n| tree.node(n).state().b2b));
+    }
+
+    #[test]
+    fn test_extend_queue() {
+        let mut tree = Tree::new(Mat::empty(), &[T], State::default());
+        let root = tree.root();
+        tree.insert(tree.generate(root));
+        assert!(tree.node(root).is_expanded());
+        tree.extend_queue(&[I]);
+        // the root could only place a T before, but now it can hold and place an I
+        assert!(!tree.node(root).is_expanded());
+        let n = tree.insert(tree.generate(root));
+        assert_eq!(n, n_positions(T) + n_positions(I));
+    }
+
+    #[test]
+    fn test_transpositions() {
+        let mut tree = Tree::new(Mat::empty(), &[T, I, O], State::default());
+        let root = tree.root();
+        tree.insert(tree.generate(root));
+        for child in tree.children(root).to_vec() {
+            tree.insert(tree.generate(child));
+        }
+        // placing T then I, or holding T and placing I then T, leads to the same
+        // positions
+        assert!(tree.transpositions() > 0);
+        let n_children = tree
+            .children(root)
+            .iter()
+            .map(|&n| tree.children(n).len())
+            .sum::<usize>();
+        assert_eq!(
+            tree.len() + tree.transpositions(),
+            1 + tree.children(root).len() + n_children
+        );
+
+        // every merged node can be reached through more than one path
+        let mut parents = vec![0; tree.len()];
+        for &child in tree.children(root) {
+            for &n in tree.children(child) {
+                parents[n.index()] += 1;
             }
         }
+        assert_eq!(
+            parents.iter().map(|&n| n.max(1) - 1).sum::<usize>(),
+            tree.transpositions()
+        );
+    }
+
+    #[test]
+    fn test_rollout() {
+        let mut tree = Tree::new(Mat::empty(), &[T], State::default());
+        let root = tree.root();
+        tree.insert(tree.generate(root));
+        let leaf = tree.children(root)[0];
+        assert_eq!(tree.rollout(leaf, &[]), tree.node(leaf).score());
+        // the last piece stays in hold, so only one piece is placed
+        assert_eq!(tree.rollout(leaf, &[O]), tree.node(leaf).score());
+        let score = tree.rollout(leaf, &[I, O]);
+        assert_ne!(score, tree.node(leaf).score());
 
-        let children = alo.alloc_slice_copy(&children);
-        self.children.set(children);
-        children
+        // placing the I piece, or the O piece and keeping the I, leads to the best score
+        let mut tree = Tree::new(tree.matrix(leaf), &[I, O], State::default());
+        let root = tree.root();
+        tree.insert(tree.generate(root));
+        let best = tree
+            .children(root)
+            .iter()
+            .map(|&n| tree.node(n).score())
+            .max();
+        assert_eq!(Some(score), best);
     }
 }
@@ -1,55 +1,488 @@
-use mino::matrix;
 use mino::matrix::Mat;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
 
+use crate::features::Features;
 use crate::state::State;
 
-pub mod weight {
-    pub const BASE: i32 = 1024; // random number to make the values generally positive
-    pub const SINGLE: i32 = -200;
-    pub const DOUBLE: i32 = -100;
-    pub const TRIPLE: i32 = 400;
-    pub const QUAD: i32 = 1024; // "reference point"; do not change
-    pub const SPIN_SINGLE: i32 = 512;
-    pub const SPIN_DOUBLE: i32 = 1200;
-    pub const SPIN_TRIPLE: i32 = 1600;
-    pub const B2B: i32 = 200;
-    // pub const B2B_BROKEN: i32
-    pub const HEIGHT: i32 = -50;
-    pub const ROW_TRANSITIONS: i32 = -200;
-    pub const BLOCKS_FROM_TARGET: i32 = -20;
-}
-
-pub fn evaluate(mat: &Mat, st: State) -> i32 {
-    let mut height = 0;
-    let mut row_trans = 0;
-    let mut block_count = 0;
-    let blocks_from_target;
-
-    {
-        let mut prev = matrix::FULL;
-        for &row in mat.rows() {
-            row_trans += (row ^ prev).count_ones() as i32;
-            block_count += row.count_ones() as i32;
-            height += 1;
-            prev = row;
+/// Weights of each feature of the evaluation function. Missing fields are filled in
+/// from the defaults when deserializing, so a weights file only needs to list the
+/// weights that are changed. Unknown fields are rejected, so that a misspelled weight is
+/// not silently replaced by its default.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EvalWeights {
+    pub base: i32,
+    pub single: i32,
+    pub double: i32,
+    pub triple: i32,
+    pub quad: i32,
+    pub spin_single: i32,
+    pub spin_double: i32,
+    pub spin_triple: i32,
+    pub b2b: i32,
+    pub combo: i32,
+    pub height: i32,
+    pub max_height: i32,
+    /// Row transitions, see [`crate::features::row_transitions`]. Weights files from
+    /// before this was split from the vertical transitions used `row_transitions` for
+    /// what is now `vertical_transitions`, so that key is rejected.
+    pub horizontal_transitions: i32,
+    pub vertical_transitions: i32,
+    pub holes: i32,
+    pub hole_depth: i32,
+    pub overhangs: i32,
+    pub bumpiness: i32,
+    pub well_depth: i32,
+    pub well_position: i32,
+    pub tsd_slots: i32,
+    pub tst_slots: i32,
+    pub blocks_from_target: i32,
+    pub garbage_queued: i32,
+    pub garbage_rows: i32,
+    pub danger: i32,
+    pub danger_height: i32,
+    /// Per line of attack sent, per row that the opponent is above `danger_height`.
+    pub opponent_danger: i32,
+    /// Per row of our stack, per line of the opponent's threat that was not cancelled by
+    /// sending attack first.
+    pub threat: i32,
+    pub dead: i32,
+}
+
+impl Default for EvalWeights {
+    fn default() -> Self {
+        Self {
+            base: 1024, // random number to make the values generally positive
+            single: -200,
+            double: -100,
+            triple: 400,
+            quad: 1024, // "reference point"; do not change
+            spin_single: 512,
+            spin_double: 1200,
+            spin_triple: 1600,
+            b2b: 200,
+            combo: 150,
+            // b2b_broken:
+            height: -50,
+            max_height: -20,
+            horizontal_transitions: -60,
+            vertical_transitions: -200,
+            holes: -120,
+            hole_depth: -15,
+            overhangs: -40,
+            bumpiness: -20,
+            well_depth: 40,
+            well_position: 0,
+            tsd_slots: 150,
+            tst_slots: 150,
+            blocks_from_target: -20,
+            garbage_queued: -80,
+            garbage_rows: -100,
+            danger: -40,
+            danger_height: 10,
+            opponent_danger: 30,
+            threat: -5,
+            dead: -1_000_000,
+        }
+    }
+}
+
+/// Error loading or saving [`EvalWeights`].
+#[derive(Debug, thiserror::Error)]
+pub enum WeightsError {
+    #[error("failed to access weights file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid weights JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid weights TOML: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("failed to write weights TOML: {0}")]
+    TomlSer(#[from] toml::ser::Error),
+}
+
+impl EvalWeights {
+    /// Load weights from a TOML file if the path ends with `.toml`, or a JSON file
+    /// otherwise.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, WeightsError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        if path.extension().is_some_and(|ext| ext == "toml") {
+            Ok(toml::from_str(&text)?)
+        } else {
+            Ok(serde_json::from_str(&text)?)
+        }
+    }
+
+    /// Save weights to a file, in the same format that [`EvalWeights::load`] would read
+    /// it in.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), WeightsError> {
+        let path = path.as_ref();
+        let text = if path.extension().is_some_and(|ext| ext == "toml") {
+            toml::to_string(self)?
+        } else {
+            serde_json::to_string_pretty(self)?
+        };
+        fs::write(path, text)?;
+        Ok(())
+    }
+}
+
+/// Contribution of a single feature to the score of a position.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Term {
+    pub name: &'static str,
+    pub value: i32,
+    pub weight: i32,
+}
+
+impl Term {
+    pub fn score(&self) -> i32 {
+        self.value * self.weight
+    }
+}
+
+/// Score of a position broken down into the contribution of each feature, as returned
+/// by [`explain`](crate::explain()).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Breakdown {
+    /// If the position is dead, the only term is the `dead` weight.
+    pub dead: bool,
+    pub terms: Vec<Term>,
+}
+
+impl Breakdown {
+    pub fn total(&self) -> i32 {
+        self.terms.iter().map(Term::score).sum()
+    }
+}
+
+impl fmt::Display for Breakdown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for term in &self.terms {
+            if term.value != 0 {
+                let Term {
+                    name,
+                    value,
+                    weight,
+                } = term;
+                let score = term.score();
+                writeln!(f, "{name:>20} {value:>6} x {weight:>8} = {score:>8}")?;
+            }
         }
-        row_trans += prev.count_ones() as i32 - 16;
-        block_count -= height * 6;
-        blocks_from_target = (block_count - 36).abs();
-    }
-
-    // trace!(height, row_trans, blocks_from_target);
-
-    weight::BASE
-        + st.single_clears as i32 * weight::SINGLE
-        + st.double_clears as i32 * weight::DOUBLE
-        + st.triple_clears as i32 * weight::TRIPLE
-        + st.quad_clears as i32 * weight::QUAD
-        + st.spin_single_clears as i32 * weight::SPIN_SINGLE
-        + st.spin_double_clears as i32 * weight::SPIN_DOUBLE
-        + st.spin_triple_clears as i32 * weight::SPIN_TRIPLE
-        + st.b2b_clears as i32 * weight::B2B
-        + height * weight::HEIGHT
-        + row_trans * weight::ROW_TRANSITIONS
-        + blocks_from_target * weight::BLOCKS_FROM_TARGET
+        let dead = if self.dead { " (dead)" } else { "" };
+        write!(f, "{:>20} {:>28}{dead}", "total", self.total())
+    }
+}
+
+fn term(name: &'static str, value: impl Into<i32>, weight: i32) -> Term {
+    let value = value.into();
+    Term {
+        name,
+        value,
+        weight,
+    }
+}
+
+// values of the terms that are not plain features or state counters
+struct Derived {
+    blocks_from_target: i32,
+    danger: i32,
+    opponent_danger: i32,
+    threat: i32,
+}
+
+impl Derived {
+    fn new(w: &EvalWeights, f: &Features, st: State) -> Self {
+        // height incl. garbage that has risen, and how far it is into the danger zone
+        let height = f.height + st.garbage_rows as i32;
+        let danger = (height - w.danger_height).max(0);
+        Self {
+            blocks_from_target: (f.blocks - 36).abs(),
+            // danger is penalized quadratically
+            danger: danger * danger,
+            // attack is worth more when the opponent is close to topping out, and a high
+            // stack is riskier when the opponent is about to send a lot of garbage
+            opponent_danger: st.attack as i32
+                * (st.opponent_height as i32 - w.danger_height).max(0),
+            threat: (st.threat as i32 - st.attack as i32).max(0) * height,
+        }
+    }
+}
+
+// the same sum as `evaluate`, with the name of each term
+fn terms(w: &EvalWeights, mat: &Mat, st: State) -> [Term; 28] {
+    let f = Features::new(mat);
+    let d = Derived::new(w, &f, st);
+    [
+        term("base", 1, w.base),
+        term("single", st.single_clears, w.single),
+        term("double", st.double_clears, w.double),
+        term("triple", st.triple_clears, w.triple),
+        term("quad", st.quad_clears, w.quad),
+        term("spin_single", st.spin_single_clears, w.spin_single),
+        term("spin_double", st.spin_double_clears, w.spin_double),
+        term("spin_triple", st.spin_triple_clears, w.spin_triple),
+        term("b2b", st.b2b_clears, w.b2b),
+        term("combo", st.combo_attack, w.combo),
+        term("height", f.height, w.height),
+        term("max_height", f.max_height, w.max_height),
+        term(
+            "horizontal_transitions",
+            f.row_transitions,
+            w.horizontal_transitions,
+        ),
+        term(
+            "vertical_transitions",
+            f.column_transitions,
+            w.vertical_transitions,
+        ),
+        term("holes", f.holes, w.holes),
+        term("hole_depth", f.hole_depth, w.hole_depth),
+        term("overhangs", f.overhangs, w.overhangs),
+        term("bumpiness", f.bumpiness, w.bumpiness),
+        term("well_depth", f.well_depth, w.well_depth),
+        term("well_position", f.well_position, w.well_position),
+        term("tsd_slots", f.tsd_slots, w.tsd_slots),
+        term("tst_slots", f.tst_slots, w.tst_slots),
+        term(
+            "blocks_from_target",
+            d.blocks_from_target,
+            w.blocks_from_target,
+        ),
+        term("garbage_queued", st.garbage, w.garbage_queued),
+        term("garbage_rows", st.garbage_rows, w.garbage_rows),
+        term("danger", d.danger, w.danger),
+        term("opponent_danger", d.opponent_danger, w.opponent_danger),
+        term("threat", d.threat, w.threat),
+    ]
+}
+
+pub fn evaluate(w: &EvalWeights, mat: &Mat, st: State) -> i32 {
+    if st.is_dead {
+        return w.dead;
+    }
+    let f = Features::new(mat);
+    let d = Derived::new(w, &f, st);
+    w.base
+        + st.single_clears as i32 * w.single
+        + st.double_clears as i32 * w.double
+        + st.triple_clears as i32 * w.triple
+        + st.quad_clears as i32 * w.quad
+        + st.spin_single_clears as i32 * w.spin_single
+        + st.spin_double_clears as i32 * w.spin_double
+        + st.spin_triple_clears as i32 * w.spin_triple
+        + st.b2b_clears as i32 * w.b2b
+        + st.combo_attack as i32 * w.combo
+        + f.height * w.height
+        + f.max_height * w.max_height
+        + f.row_transitions * w.horizontal_transitions
+        + f.column_transitions * w.vertical_transitions
+        + f.holes * w.holes
+        + f.hole_depth * w.hole_depth
+        + f.overhangs * w.overhangs
+        + f.bumpiness * w.bumpiness
+        + f.well_depth * w.well_depth
+        + f.well_position * w.well_position
+        + f.tsd_slots * w.tsd_slots
+        + f.tst_slots * w.tst_slots
+        + d.blocks_from_target * w.blocks_from_target
+        + st.garbage as i32 * w.garbage_queued
+        + st.garbage_rows as i32 * w.garbage_rows
+        + d.danger * w.danger
+        + d.opponent_danger * w.opponent_danger
+        + d.threat * w.threat
+}
+
+/// Returns the contribution of each feature to the score of a position, such that
+/// `explain(w, mat, st).total() == evaluate(w, mat, st)`.
+pub fn explain(w: &EvalWeights, mat: &Mat, st: State) -> Breakdown {
+    if st.is_dead {
+        return Breakdown {
+            dead: true,
+            terms: vec![term("dead", 1, w.dead)],
+        };
+    }
+    Breakdown {
+        dead: false,
+        terms: terms(w, mat, st).to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mino::Spin;
+
+    #[test]
+    fn test_weights_json_round_trip() {
+        let weights = EvalWeights {
+            height: -70,
+            ..EvalWeights::default()
+        };
+        let json = serde_json::to_string(&weights).unwrap();
+        assert_eq!(serde_json::from_str::<EvalWeights>(&json).unwrap(), weights);
+    }
+
+    #[test]
+    fn test_weights_partial() {
+        let weights: EvalWeights = toml::from_str("combo = 300\ndanger_height = 8\n").unwrap();
+        assert_eq!(
+            weights,
+            EvalWeights {
+                combo: 300,
+                danger_height: 8,
+                ..EvalWeights::default()
+            }
+        );
+        let weights: EvalWeights = serde_json::from_str("{}").unwrap();
+        assert_eq!(weights, EvalWeights::default());
+    }
+
+    #[test]
+    fn test_weights_unknown_field() {
+        assert!(toml::from_str::<EvalWeights>("combo = 300\ncmobo = 300\n").is_err());
+        assert!(serde_json::from_str::<EvalWeights>(r#"{"hieght": -70}"#).is_err());
+        // renamed when its meaning changed
+        assert!(toml::from_str::<EvalWeights>("row_transitions = -200\n").is_err());
+    }
+
+    #[test]
+    fn test_weights_load() {
+        let dir = std::env::temp_dir();
+        let toml_path = dir.join(format!("bluefin-weights-{}.toml", std::process::id()));
+        let json_path = dir.join(format!("bluefin-weights-{}.json", std::process::id()));
+        fs::write(&toml_path, "b2b = 250").unwrap();
+        fs::write(&json_path, r#"{"b2b": 250}"#).unwrap();
+        let from_toml = EvalWeights::load(&toml_path);
+        let from_json = EvalWeights::load(&json_path);
+        fs::remove_file(&toml_path).unwrap();
+        fs::remove_file(&json_path).unwrap();
+        assert_eq!(from_toml.unwrap().b2b, 250);
+        assert_eq!(from_json.unwrap().b2b, 250);
+
+        let weights = EvalWeights {
+            danger: -55,
+            ..EvalWeights::default()
+        };
+        for path in [&toml_path, &json_path] {
+            weights.save(path).unwrap();
+            let loaded = EvalWeights::load(path);
+            fs::remove_file(path).unwrap();
+            assert_eq!(loaded.unwrap(), weights);
+        }
+        assert!(matches!(
+            EvalWeights::load(dir.join("bluefin-weights-missing.json")),
+            Err(WeightsError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_evaluate_uses_weights() {
+        let mat = Mat::new(&[mino::matrix::EMPTY | 0b1111]);
+        let st = State::default();
+        let default = evaluate(&EvalWeights::default(), mat, st);
+        let higher = EvalWeights {
+            height: EvalWeights::default().height - 10,
+            ..EvalWeights::default()
+        };
+        assert_eq!(evaluate(&higher, mat, st), default - 10);
+    }
+
+    #[test]
+    fn test_explain() {
+        let w = EvalWeights::default();
+        let mat = Mat::new(&[
+            mino::matrix::EMPTY | 0b1111111110,
+            mino::matrix::EMPTY | 0b1,
+        ]);
+        let st = State::new(true, 2, 3).next(1, Spin::None);
+        let breakdown = explain(&w, mat, st);
+        assert!(!breakdown.dead);
+        assert_eq!(breakdown.total(), evaluate(&w, mat, st));
+        let height = breakdown.terms.iter().find(|t| t.name == "height").unwrap();
+        assert_eq!((height.value, height.weight), (2, w.height));
+
+        let dead = State {
+            is_dead: true,
+            ..st
+        };
+        let breakdown = explain(&w, mat, dead);
+        assert!(breakdown.dead);
+        assert_eq!(breakdown.total(), w.dead);
+    }
+
+    #[test]
+    fn test_explain_agrees() {
+        // a different weight for every key, so a term missing from either sum shows up
+        let mut json = serde_json::to_value(EvalWeights::default()).unwrap();
+        for (i, v) in json.as_object_mut().unwrap().values_mut().enumerate() {
+            *v = (i as i32 * 7 + 3).into();
+        }
+        let distinct: EvalWeights = serde_json::from_value(json).unwrap();
+        let mats = [
+            Mat::empty(),
+            Mat::new(&[
+                mino::matrix::EMPTY | 0b1111111110,
+                mino::matrix::EMPTY | 0b1011100001,
+                mino::matrix::EMPTY | 0b0000100000,
+            ]),
+            Mat::new(&[mino::matrix::EMPTY | 0b1101111111; 14]),
+        ];
+        let opponent = Some(crate::Opponent {
+            height: 16,
+            garbage: 3,
+            threat: 8,
+        });
+        let states = [
+            State::default(),
+            State::new(true, 2, 3).next(1, Spin::None),
+            State::new(true, 0, 5)
+                .next(2, Spin::Full)
+                .next(1, Spin::Mini)
+                .next(4, Spin::None)
+                .with_opponent(opponent),
+            State {
+                garbage_rows: 4,
+                ..State::new(false, 1, 0).next(3, Spin::Full)
+            },
+        ];
+        for w in [EvalWeights::default(), distinct] {
+            for mat in mats {
+                for st in states {
+                    assert_eq!(explain(&w, mat, st).total(), evaluate(&w, mat, st));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_opponent() {
+        let w = EvalWeights::default();
+        let mat = Mat::new(&[mino::matrix::EMPTY | 0b1111]);
+        let attack = State::new(false, 0, 0).next(4, Spin::None);
+        let high = Some(crate::Opponent {
+            height: 15,
+            garbage: 2,
+            threat: 0,
+        });
+        let low = evaluate(&w, mat, attack);
+        let high = evaluate(&w, mat, attack.with_opponent(high));
+        assert_eq!(high - low, 4 * (17 - w.danger_height) * w.opponent_danger);
+
+        // sending attack first cancels the threat
+        let spike = Some(crate::Opponent {
+            threat: 6,
+            ..Default::default()
+        });
+        let st = State::default().with_opponent(spike);
+        assert_eq!(
+            evaluate(&w, mat, st),
+            evaluate(&w, mat, State::default()) + 6 * w.threat
+        );
+        let st = attack.with_opponent(spike);
+        assert_eq!(evaluate(&w, mat, st), low + 2 * w.threat);
+    }
 }
@@ -0,0 +1,73 @@
+//! Explaining the decisions of the search: the line of play it expects after the chosen
+//! move, and how the position at the end of that line was evaluated.
+
+use mino::matrix::{Mat, COLS};
+use mino::standard_rules::FallingPiece;
+use mino::Spin;
+use std::fmt;
+
+use crate::dag::{NodeId, Tree};
+use crate::eval::{self, Breakdown};
+use crate::plan::{line, Step};
+
+/// The best line of play found by the last search, starting with the chosen move, and
+/// the evaluation of the position at its end.
+#[derive(Clone)]
+pub struct Explanation {
+    pub steps: Vec<Step>,
+    pub breakdown: Breakdown,
+}
+
+impl Explanation {
+    pub(crate) fn new(tree: &Tree, leaf: NodeId, hold_empty: bool) -> Self {
+        let node = tree.node(leaf);
+        Self {
+            steps: line(tree, &tree.path(leaf), hold_empty),
+            breakdown: eval::explain(tree.weights(), tree.matrix(leaf), node.state()),
+        }
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, step) in self.steps.iter().enumerate() {
+            let hold = if step.hold { "hold, " } else { "" };
+            let spin = match step.spin {
+                Spin::Full => " spin",
+                Spin::Mini => " mini",
+                Spin::None => "",
+            };
+            writeln!(
+                f,
+                "{}. {hold}{:?}{spin}, cleared {}, score {}",
+                i + 1,
+                step.piece,
+                step.cleared,
+                step.score,
+            )?;
+            render(f, step.matrix(), step.piece)?;
+        }
+        write!(f, "{}", self.breakdown)
+    }
+}
+
+// draws the matrix with the piece on top of it, which is shown by its name
+fn render(f: &mut fmt::Formatter<'_>, matrix: &Mat, piece: FallingPiece) -> fmt::Result {
+    let cells = piece.cells().coords().collect::<Vec<_>>();
+    let top = cells.iter().map(|&(_, y)| y + 1).max().unwrap_or(0);
+    for y in (0..matrix.len().max(top)).rev() {
+        let row = (0..COLS)
+            .map(|x| {
+                if cells.contains(&(x, y)) {
+                    piece.piece.name()
+                } else if matrix.get(y) & (1 << x) != 0 {
+                    "#"
+                } else {
+                    " "
+                }
+            })
+            .collect::<String>();
+        writeln!(f, "|{row}|")?;
+    }
+    writeln!(f, "+----------+")
+}
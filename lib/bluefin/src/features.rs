@@ -0,0 +1,282 @@
+//! Features of the matrix used by the evaluation function. Each feature is computed by a
+//! separate function so that it can be tested and logged on its own; [`Features`]
+//! collects all of them.
+
+use mino::matrix::{Mat, COLS, EMPTY, FULL};
+use mino::tspin;
+
+const N_COLS: usize = COLS as usize;
+
+/// All of the features of a matrix.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Features {
+    /// Number of rows in the matrix.
+    pub height: i32,
+    /// Height of the tallest column.
+    pub max_height: i32,
+    /// Number of filled cells.
+    pub blocks: i32,
+    pub row_transitions: i32,
+    pub column_transitions: i32,
+    pub holes: i32,
+    pub hole_depth: i32,
+    pub overhangs: i32,
+    pub bumpiness: i32,
+    pub well_depth: i32,
+    /// Distance between the well column and the nearest wall.
+    pub well_position: i32,
+    /// Number of T-spin double and triple slots that the T piece can reach, see
+    /// [`spin_slots`].
+    pub tsd_slots: i32,
+    pub tst_slots: i32,
+}
+
+impl Features {
+    pub fn new(mat: &Mat) -> Self {
+        let heights = column_heights(mat);
+        let (holes, hole_depth) = holes(mat, &heights);
+        let well = well(&heights);
+        let (tsd_slots, tst_slots) = spin_slots(mat);
+        Self {
+            height: mat.len() as i32,
+            max_height: max_height(&heights),
+            blocks: blocks(mat),
+            row_transitions: row_transitions(mat),
+            column_transitions: column_transitions(mat),
+            holes,
+            hole_depth,
+            overhangs: overhangs(mat),
+            bumpiness: bumpiness(&heights, well.map(|(x, _)| x)),
+            well_depth: well.map_or(0, |(_, depth)| depth),
+            well_position: well.map_or(0, |(x, _)| x.min(N_COLS - 1 - x) as i32),
+            tsd_slots,
+            tst_slots,
+        }
+    }
+}
+
+/// Height of each column, i.e. one above its highest filled cell.
+pub fn column_heights(mat: &Mat) -> [i32; N_COLS] {
+    let mut heights = [0; N_COLS];
+    for (y, &row) in mat.rows().iter().enumerate() {
+        for (x, h) in heights.iter_mut().enumerate() {
+            if row & (1 << x) != 0 {
+                *h = y as i32 + 1;
+            }
+        }
+    }
+    heights
+}
+
+pub fn max_height(heights: &[i32; N_COLS]) -> i32 {
+    heights.iter().copied().max().unwrap_or(0)
+}
+
+pub fn blocks(mat: &Mat) -> i32 {
+    mat.rows()
+        .iter()
+        .map(|&row| (row & !EMPTY).count_ones() as i32)
+        .sum()
+}
+
+/// Number of horizontally adjacent pairs of cells where one is filled and the other is
+/// empty, counting the walls as filled.
+pub fn row_transitions(mat: &Mat) -> i32 {
+    mat.rows()
+        .iter()
+        .map(|&row| {
+            // shift in a filled cell from the left wall
+            let shifted = (row << 1) | 1;
+            ((row ^ shifted) & !(EMPTY << 1)).count_ones() as i32
+        })
+        .sum()
+}
+
+/// Number of vertically adjacent pairs of cells where one is filled and the other is
+/// empty, counting the floor as filled. The empty space above the matrix is not counted.
+pub fn column_transitions(mat: &Mat) -> i32 {
+    let mut prev = FULL;
+    let mut count = 0;
+    for &row in mat.rows() {
+        count += ((row ^ prev) & !EMPTY).count_ones() as i32;
+        prev = row;
+    }
+    count
+}
+
+/// Returns the number of empty cells that have a filled cell somewhere above them, and
+/// the total number of filled cells above those holes.
+pub fn holes(mat: &Mat, heights: &[i32; N_COLS]) -> (i32, i32) {
+    let mut holes = 0;
+    let mut depth = 0;
+    for (x, &h) in heights.iter().enumerate() {
+        let mut filled_above = 0;
+        for y in (0..h).rev() {
+            if mat.get(y as i8) & (1 << x) != 0 {
+                filled_above += 1;
+            } else {
+                holes += 1;
+                depth += filled_above;
+            }
+        }
+    }
+    (holes, depth)
+}
+
+/// Number of empty cells directly below a filled cell.
+pub fn overhangs(mat: &Mat) -> i32 {
+    let rows = mat.rows();
+    rows.windows(2)
+        .map(|w| (!w[0] & w[1] & !EMPTY).count_ones() as i32)
+        .sum()
+}
+
+/// Returns the lowest column, which is left open to clear lines in, and how much lower
+/// it is than the lower of its neighbours. Returns `None` if the matrix is empty.
+pub fn well(heights: &[i32; N_COLS]) -> Option<(usize, i32)> {
+    let (x, &h) = heights.iter().enumerate().min_by_key(|&(_, h)| *h)?;
+    let left = x.checked_sub(1).map_or(i32::MAX, |l| heights[l]);
+    let right = heights.get(x + 1).copied().unwrap_or(i32::MAX);
+    let depth = left.min(right) - h;
+    (depth > 0).then_some((x, depth))
+}
+
+/// Sum of the height differences between adjacent columns, ignoring the well column.
+pub fn bumpiness(heights: &[i32; N_COLS], well: Option<usize>) -> i32 {
+    let mut sum = 0;
+    let mut prev: Option<i32> = None;
+    for (x, &h) in heights.iter().enumerate() {
+        if Some(x) == well {
+            continue;
+        }
+        if let Some(p) = prev {
+            sum += (h - p).abs();
+        }
+        prev = Some(h);
+    }
+    sum
+}
+
+/// Number of T-spin double and T-spin triple slots that the T piece can reach, see
+/// [`tspin::slots`].
+pub fn spin_slots(mat: &Mat) -> (i32, i32) {
+    // rule out most matrices without the much slower search for slots: to clear two
+    // adjacent rows, a T has to fill 3 or 4 missing cells within 3 columns of them, and to
+    // clear the rows above and below its center, it has to stand on its side in a column
+    // that both are missing
+    let missing = |y: i8| FULL & !mat.get(y);
+    let fits = (0..mat.len()).any(|y| {
+        let cells = missing(y) | missing(y + 1);
+        if cells == 0 {
+            return false;
+        }
+        let span = 16 - cells.leading_zeros() - cells.trailing_zeros();
+        let stem = missing(y);
+        let adjacent = (3..=4).contains(&cells.count_ones()) && span <= 3;
+        let apart = stem.count_ones() == 1
+            && missing(y + 2) == stem
+            && missing(y + 1) & stem != 0
+            && missing(y + 1) & (stem << 1 | stem >> 1) != 0;
+        adjacent || apart
+    });
+    if !fits {
+        return (0, 0);
+    }
+    let targets = tspin::targets(mat);
+    let count = |n| targets.iter().filter(|&&(_, cleared)| cleared == n).count() as i32;
+    (count(2), count(3))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mino::MatBuf;
+
+    // rows are given from top to bottom, like they are displayed
+    fn mat(rows: &[&str]) -> MatBuf {
+        let mut mat = MatBuf::new();
+        for (y, row) in rows.iter().rev().enumerate() {
+            let bits = row
+                .chars()
+                .enumerate()
+                .filter(|&(_, c)| c == 'x')
+                .fold(0, |bits, (x, _)| bits | (1 << x));
+            mat.set(y as i8, bits);
+        }
+        mat
+    }
+
+    #[test]
+    fn test_heights() {
+        let m = mat(&["..x.......", "x.x......x"]);
+        assert_eq!(column_heights(&m), [1, 0, 2, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(max_height(&column_heights(&m)), 2);
+        assert_eq!(blocks(&m), 4);
+    }
+
+    #[test]
+    fn test_transitions() {
+        let m = mat(&["x.........", "xxxxxxxx.x"]);
+        // bottom row: either side of the hole -> 2; top row: filled then empty, and empty
+        // next to the right wall -> 2
+        assert_eq!(row_transitions(&m), 4);
+        // column 8: floor then empty -> 1; columns 1-7 and 9: filled then empty -> 8
+        assert_eq!(column_transitions(&m), 9);
+        assert_eq!(row_transitions(Mat::empty()), 0);
+        assert_eq!(column_transitions(Mat::empty()), 0);
+    }
+
+    #[test]
+    fn test_holes() {
+        let m = mat(&["xx........", "x.........", ".x.......x"]);
+        let heights = column_heights(&m);
+        // (0, 0) is under 2 filled cells, (1, 1) is under 1
+        assert_eq!(holes(&m, &heights), (2, 3));
+        // (0, 0) has a filled cell directly above, (1, 1) too
+        assert_eq!(overhangs(&m), 2);
+    }
+
+    #[test]
+    fn test_well() {
+        let m = mat(&["xxx.xxxxxx", "xxx.xxxxx."]);
+        let heights = column_heights(&m);
+        assert_eq!(well(&heights), Some((3, 2)));
+        assert_eq!(bumpiness(&heights, Some(3)), 0);
+        assert_eq!(bumpiness(&heights, None), 4);
+        let f = Features::new(&m);
+        assert_eq!((f.well_depth, f.well_position), (2, 3));
+
+        // a well at the edge is bounded by the wall
+        let m = mat(&["xxxxxxxxx."]);
+        assert_eq!(well(&column_heights(&m)), Some((9, 1)));
+        assert_eq!(well(&column_heights(Mat::empty())), None);
+    }
+
+    #[test]
+    fn test_spin_slots() {
+        let m = mat(&["x.........", "...xxxxxxx", "x.xxxxxxxx"]);
+        assert_eq!(spin_slots(&m), (1, 0));
+        // no overhang, so the T can be dropped in without spinning
+        let m = mat(&["...xxxxxxx", "x.xxxxxxxx"]);
+        assert_eq!(spin_slots(&m), (0, 0));
+        // the bottom row has another hole
+        let m = mat(&["x.........", "...xxxxxxx", "x.xxxx.xxx"]);
+        assert_eq!(spin_slots(&m), (0, 0));
+
+        // a T-shaped hole that the T cannot be spun into
+        let m = mat(&["xx.xxxxxxx", "xx..xxxxxx", "xx.xxxxxxx"]);
+        assert_eq!(spin_slots(&m), (0, 0));
+        let m = mat(&[
+            "xxxx......",
+            "xxx.......",
+            "xxx.xxxxxx",
+            "xxx..xxxxx",
+            "xxx.xxxxxx",
+        ]);
+        assert_eq!(spin_slots(&m).1, 1);
+
+        // full rows are not missing any cells
+        let m = mat(&["xxxxxxxxxx", "xxxxxxxxxx"]);
+        assert_eq!(spin_slots(&m), (0, 0));
+    }
+}
@@ -1,95 +1,66 @@
 #[macro_use]
 extern crate tracing;
 
-pub use bumpalo::Bump as Alloc;
-
-use std::mem::swap;
-
-use mino::input::Input;
 use mino::matrix::Mat;
-use mino::places::reach;
-use mino::standard_rules::{Piece, Queue};
+use mino::standard_rules::Piece;
 
+mod bag;
 mod dag;
 mod eval;
+mod explain;
+pub mod features;
+mod opener;
+mod opponent;
+mod outcome;
+mod pc;
+mod plan;
+mod search;
+mod session;
 mod state;
 
-const INITIAL_HEAP_CAPACITY: usize = 32 * 1024 * 1024;
-const INITIAL_BEAM_CAPACITY: usize = 32 * 1024 * 1024;
+pub use eval::{Breakdown, EvalWeights, Term, WeightsError};
+pub use explain::Explanation;
+pub use opener::{Book, Opener, OpenerError};
+pub use opponent::Opponent;
+pub use outcome::{NoMove, Outcome, Stats};
+pub use plan::{Plan, Step};
+pub use search::{Limits, Sampling};
+pub use session::Session;
+
+#[cfg(feature = "botris")]
+pub mod player;
+#[cfg(feature = "botris")]
+pub use player::Bluefin;
+
+/// Game information needed by the search besides the matrix and queue.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Context {
+    /// Back-to-back status; the previous clear was a quad or spin.
+    pub b2b: bool,
+    /// Current combo counter; number of consecutive line clears so far.
+    pub combo: u32,
+    /// Number of garbage lines queued to be received.
+    pub garbage: u32,
+    /// What is known about the opponent in a versus game.
+    pub opponent: Option<Opponent>,
+}
 
+/// Search for the best move from scratch. Use a [`Session`] instead to reuse the search
+/// tree between moves.
 pub fn bot(
     current: Piece,
     queue: &[Piece],
     hold: Option<Piece>,
     matrix: &Mat,
-    // b2b: bool,
-    // ren: u32,
-    // jeopardy: [u32; 2],
-) -> Option<(bool, Vec<Input>)> {
-    let combined_queue_pieces: Vec<Piece> = [hold.as_slice(), &[current], queue]
-        .into_iter()
-        .flat_map(|x| x.iter().copied())
-        .collect();
-    let queue = Queue::from(&*combined_queue_pieces);
-
-    debug!("start {}", queue);
-
-    let alo = Alloc::with_capacity(INITIAL_HEAP_CAPACITY);
-
-    let mut beam = Vec::with_capacity(INITIAL_BEAM_CAPACITY);
-    let mut next_beam = Vec::with_capacity(INITIAL_BEAM_CAPACITY);
-
-    let b2b = false; // TODO
-    let root = dag::Node::root(&alo, matrix, queue, b2b);
-
-    let mut total_expanded = 1;
-
-    let mut best = root;
-    //let mut best_expanded = 1;
-    let mut best_generation = 0;
-
-    for generation in 0..5 {
-        let beam_width = 1 << (4 + generation); // 16, 32, 64, 128, 256
-        trace!(generation, beam_width, total_expanded);
-        beam.clear();
-        beam.push(root);
-
-        loop {
-            if beam.len() > beam_width {
-                beam.select_nth_unstable_by_key(beam_width, |n| n.ord_min_best());
-                beam.truncate(beam_width);
-            }
-
-            next_beam.clear();
-            for &node in beam.iter() {
-                if node.children().is_empty() {
-                    total_expanded += node.expand(&alo).len();
-                }
-                next_beam.extend_from_slice(node.children());
-            }
-
-            if next_beam.is_empty() {
-                for &node in beam.iter() {
-                    if node.ord_max_best() > best.ord_max_best() {
-                        best = node;
-                        best_generation = generation;
-                        trace!(new_best = best.score(), generation, total_expanded);
-                    }
-                }
-                break;
-            }
-
-            swap(&mut beam, &mut next_beam);
-        }
-    }
-
-    let target = best.original_piece()?;
-
-    debug!(best = best.score(), ?target, state = ?best.state());
-    debug!(total_expanded, best_generation);
-    trace!(alo_kb = alo.allocated_bytes() / 1024);
+    ctx: Context,
+    limits: &Limits,
+) -> Outcome {
+    Session::new().search(current, queue, hold, matrix, ctx, limits)
+}
 
-    let hold = target.piece != current;
-    let reach_inputs = reach(matrix, target)?;
-    Some((hold, reach_inputs))
+/// Evaluate a position on its own, without searching, and return the contribution of
+/// each feature to its score.
+pub fn explain(weights: &EvalWeights, matrix: &Mat, ctx: Context) -> Breakdown {
+    let state = state::State::new(ctx.b2b, ctx.combo, ctx.garbage).with_opponent(ctx.opponent);
+    eval::explain(weights, matrix, state)
 }
@@ -0,0 +1,383 @@
+//! Openers: fixed shapes to build at the start of a round, instead of searching from an
+//! empty matrix.
+//!
+//! A book of openers is loaded from a TOML or JSON file such as:
+//!
+//! ```toml
+//! [[openers]]
+//! name = "TSD"
+//! # rows of the shape from the top down; letters are the cells filled by each piece
+//! shape = [
+//!     "...Z......",
+//!     "OOZZ..JLS.",
+//!     "OOZ...JLSS",
+//!     "IIII.JJLLS",
+//! ]
+//! # the I piece must come before the Z piece in the first bag
+//! conditions = ["I<Z"]
+//! ```
+
+use mino::matrix::{Mat, MatBuf, COLS, EMPTY};
+use mino::places::places;
+use mino::standard_rules::{FallingPiece, Piece, Queue};
+use mino::SpinRules;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+use crate::eval::EvalWeights;
+use crate::plan::{self, Step};
+use crate::state::State;
+
+/// Error loading a [`Book`], or creating an [`Opener`].
+#[derive(Debug, thiserror::Error)]
+pub enum OpenerError {
+    #[error("failed to read openers file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid openers JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid openers TOML: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("invalid shape of opener {0:?}: {1}")]
+    Shape(String, &'static str),
+    #[error("invalid condition {1:?} of opener {0:?}, expected e.g. \"I<Z\"")]
+    Condition(String, String),
+}
+
+/// An opener: a shape built by placing the pieces of the first bags, with conditions on
+/// the order in which they must come. Pieces are only ever placed on their cells of the
+/// shape, so it must be possible to build it without clearing lines.
+#[derive(Clone, Debug)]
+pub struct Opener {
+    name: String,
+    targets: Vec<Target>,
+    // (a, b): piece a comes before piece b
+    conditions: Vec<(Piece, Piece)>,
+}
+
+// cells of the shape filled by a single piece
+#[derive(Clone, Debug)]
+struct Target {
+    piece: Piece,
+    cells: [(i8, i8); 4],
+}
+
+#[derive(Deserialize)]
+struct OpenerFile {
+    name: String,
+    shape: Vec<String>,
+    #[serde(default)]
+    conditions: Vec<String>,
+}
+
+impl Opener {
+    /// Create an opener from the rows of its shape, from the top down, where each piece
+    /// is drawn as 4 connected cells with its name and empty cells are `.`. Pieces of the
+    /// same type must not touch each other. Each condition `"A<B"` requires piece A to
+    /// come before piece B, counting the current piece and the queue.
+    pub fn new(name: &str, shape: &[&str], conditions: &[&str]) -> Result<Self, OpenerError> {
+        let shape_err = |why| OpenerError::Shape(name.to_string(), why);
+
+        let mut grid = Vec::with_capacity(shape.len());
+        for row in shape.iter().rev() {
+            if row.len() != COLS as usize {
+                return Err(shape_err("rows must be 10 cells wide"));
+            }
+            let cells = row
+                .chars()
+                .map(|c| match c {
+                    '.' => Some(None),
+                    c => parse_piece(c).map(Some),
+                })
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| shape_err("cells must be '.' or a piece name"))?;
+            if cells.iter().all(Option::is_some) {
+                return Err(shape_err("rows must not be full"));
+            }
+            grid.push(cells);
+        }
+
+        let mut targets = Vec::new();
+        for y in 0..grid.len() {
+            for x in 0..grid[y].len() {
+                let Some(piece) = grid[y][x] else { continue };
+                let group = take_group(&mut grid, x, y, piece);
+                let cells = <[(i8, i8); 4]>::try_from(group)
+                    .map_err(|_| shape_err("each piece must have exactly 4 cells"))?;
+                targets.push(Target { piece, cells });
+            }
+        }
+
+        let conditions = conditions
+            .iter()
+            .map(|cond| {
+                let mut chars = cond.chars();
+                match (chars.next(), chars.next(), chars.next(), chars.next()) {
+                    (Some(a), Some('<'), Some(b), None) => parse_piece(a).zip(parse_piece(b)),
+                    _ => None,
+                }
+                .ok_or_else(|| OpenerError::Condition(name.to_string(), cond.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            name: name.to_string(),
+            targets,
+            conditions,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns `true` if the opener can be started with the queue: its conditions hold,
+    /// and the pieces of the queue can all be placed on their cells of the shape.
+    pub fn is_viable(&self, matrix: &Mat, queue: Queue) -> bool {
+        let order = queue
+            .hold()
+            .into_iter()
+            .chain(queue.next().iter().copied())
+            .collect::<Vec<_>>();
+        let position = |pc| order.iter().position(|&x| x == pc).unwrap_or(usize::MAX);
+        let conditions = self.conditions.iter().all(|&(a, b)| {
+            let (a, b) = (position(a), position(b));
+            a < b
+        });
+        conditions && self.placements(matrix, queue).is_some()
+    }
+
+    /// Returns the placements that continue building the shape with the pieces of the
+    /// queue, or `None` if the shape is complete or cannot be continued.
+    pub(crate) fn placements(&self, matrix: &Mat, queue: Queue) -> Option<Vec<FallingPiece>> {
+        let mut placed = Vec::with_capacity(self.targets.len());
+        for target in &self.targets {
+            let filled = target
+                .cells
+                .iter()
+                .filter(|&&(x, y)| matrix.get(y) & (1 << x) != 0)
+                .count();
+            match filled {
+                0 => placed.push(false),
+                4 => placed.push(true),
+                _ => return None,
+            }
+        }
+        let mut shape = MatBuf::new();
+        for target in &self.targets {
+            target.cells.iter().for_each(|&(x, y)| {
+                shape.set(y, shape.get(y) | 1 << x);
+            });
+        }
+        let outside = (0..matrix.len()).any(|y| matrix.get(y) & !shape.get(y) & !EMPTY != 0);
+        if outside || placed.iter().all(|&p| p) {
+            return None;
+        }
+
+        let mut path = Vec::new();
+        let found = self.build(matrix, queue, &mut placed, &mut path);
+        (found && !path.is_empty()).then_some(path)
+    }
+
+    // place pieces of the queue on their targets until the shape or the queue runs out
+    fn build(
+        &self,
+        matrix: &Mat,
+        queue: Queue,
+        placed: &mut [bool],
+        path: &mut Vec<FallingPiece>,
+    ) -> bool {
+        if queue.next().is_empty() || placed.iter().all(|&p| p) {
+            return true;
+        }
+        let mut new_matrix = MatBuf::new();
+        for (pc, next_queue) in queue.pop() {
+            for pl in places(matrix, pc) {
+                let mut cells = pl.cells.coords().collect::<Vec<_>>();
+                cells.sort();
+                let Some(i) = (0..self.targets.len()).find(|&i| {
+                    !placed[i] && self.targets[i].piece == pc && self.targets[i].cells == *cells
+                }) else {
+                    continue;
+                };
+
+                new_matrix.copy_from(matrix);
+                new_matrix.place(pl.cells);
+                placed[i] = true;
+                path.push(pl.falling_piece);
+                if self.build(&new_matrix, next_queue, placed, path) {
+                    return true;
+                }
+                path.pop();
+                placed[i] = false;
+            }
+        }
+        false
+    }
+}
+
+// removes the connected cells of the same piece starting at (x, y) from the grid, and
+// returns their coordinates in sorted order
+fn take_group(grid: &mut [Vec<Option<Piece>>], x: usize, y: usize, piece: Piece) -> Vec<(i8, i8)> {
+    let mut group = Vec::new();
+    let mut stack = vec![(x, y)];
+    grid[y][x] = None;
+    while let Some((x, y)) = stack.pop() {
+        group.push((x as i8, y as i8));
+        let neighbors = [
+            (x.wrapping_sub(1), y),
+            (x + 1, y),
+            (x, y.wrapping_sub(1)),
+            (x, y + 1),
+        ];
+        for (nx, ny) in neighbors {
+            let cell = grid.get_mut(ny).and_then(|row| row.get_mut(nx));
+            if let Some(cell) = cell.filter(|cell| **cell == Some(piece)) {
+                *cell = None;
+                stack.push((nx, ny));
+            }
+        }
+    }
+    group.sort();
+    group
+}
+
+fn parse_piece(c: char) -> Option<Piece> {
+    Piece::ALL.into_iter().find(|pc| pc.name().starts_with(c))
+}
+
+/// A collection of openers, in order of preference.
+#[derive(Clone, Debug, Default)]
+pub struct Book {
+    openers: Vec<Opener>,
+}
+
+#[derive(Deserialize)]
+struct BookFile {
+    openers: Vec<OpenerFile>,
+}
+
+impl Book {
+    pub fn new(openers: Vec<Opener>) -> Self {
+        Self { openers }
+    }
+
+    /// Load a book from a TOML file if the path ends with `.toml`, or a JSON file
+    /// otherwise.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, OpenerError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let file: BookFile = if path.extension().is_some_and(|ext| ext == "toml") {
+            toml::from_str(&text)?
+        } else {
+            serde_json::from_str(&text)?
+        };
+        let openers = file
+            .openers
+            .iter()
+            .map(|o| {
+                let shape = o.shape.iter().map(String::as_str).collect::<Vec<_>>();
+                let conditions = o.conditions.iter().map(String::as_str).collect::<Vec<_>>();
+                Opener::new(&o.name, &shape, &conditions)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { openers })
+    }
+
+    pub fn openers(&self) -> &[Opener] {
+        &self.openers
+    }
+
+    /// Returns the first opener that is viable with the queue, see [`Opener::is_viable`].
+    pub fn find(&self, matrix: &Mat, queue: Queue) -> Option<&Opener> {
+        self.openers.iter().find(|o| o.is_viable(matrix, queue))
+    }
+}
+
+/// Returns the line of play that continues building `opener`, using the queue `pieces`
+/// (see [`plan::line`]), or `None` if the opener is finished or was broken.
+pub(crate) fn line(
+    opener: &Opener,
+    matrix: &Mat,
+    pieces: &[Piece],
+    hold_empty: bool,
+    state: State,
+    weights: &EvalWeights,
+    spin_rules: SpinRules,
+) -> Option<Vec<Step>> {
+    let placements = opener.placements(matrix, Queue::from(pieces))?;
+    plan::simulate(
+        matrix,
+        pieces,
+        hold_empty,
+        state,
+        weights,
+        spin_rules,
+        &placements,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mino::standard_rules::Piece::*;
+
+    const TSD: [&str; 4] = ["...Z......", "OOZZ..JLS.", "OOZ...JLSS", "IIII.JJLLS"];
+
+    #[test]
+    fn test_new() {
+        let opener = Opener::new("TSD", &TSD, &["I<Z"]).unwrap();
+        assert_eq!(opener.targets.len(), 6);
+        assert_eq!(opener.conditions, [(I, Z)]);
+        let i = opener.targets.iter().find(|t| t.piece == I).unwrap();
+        assert_eq!(i.cells, [(0, 0), (1, 0), (2, 0), (3, 0)]);
+
+        assert!(matches!(
+            Opener::new("bad", &["IIII......", "OO........"], &[]),
+            Err(OpenerError::Shape(..))
+        ));
+        assert!(matches!(
+            Opener::new("bad", &TSD, &["I>Z"]),
+            Err(OpenerError::Condition(..))
+        ));
+    }
+
+    #[test]
+    fn test_build() {
+        let opener = Opener::new("TSD", &TSD, &["I<Z"]).unwrap();
+        let pieces = [T, I, J, L, O, S, Z];
+        let queue = Queue::from(&pieces);
+        assert!(opener.is_viable(Mat::empty(), queue));
+        // the Z comes first
+        let pieces = [Z, I, J, L, O, S, T];
+        assert!(!opener.is_viable(Mat::empty(), Queue::from(&pieces)));
+
+        // place the whole shape, holding the T
+        let pieces = [T, I, J, L, O, S, Z];
+        let placements = opener.placements(Mat::empty(), queue).unwrap();
+        assert_eq!(placements.len(), 6);
+        let weights = EvalWeights::default();
+        let state = State::new(false, 0, 0);
+        let line = line(
+            &opener,
+            Mat::empty(),
+            &pieces,
+            true,
+            state,
+            &weights,
+            SpinRules::default(),
+        )
+        .unwrap();
+        assert!(line[0].hold);
+        let mut matrix = MatBuf::new();
+        for step in &line {
+            matrix.place(step.piece.cells());
+        }
+        assert_eq!(opener.placements(&matrix, Queue::from(&[T])), None);
+
+        // a cell outside of the shape
+        matrix.clear();
+        matrix.set(0, EMPTY | 1 << 4);
+        assert_eq!(opener.placements(&matrix, queue), None);
+    }
+}
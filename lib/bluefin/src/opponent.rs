@@ -0,0 +1,118 @@
+//! Model of an opponent in a versus game, used to decide between attacking and defending.
+
+use mino::matrix::{Mat, FULL};
+use mino::tspin;
+
+use crate::features;
+use crate::state::{B2B_ATTACK, CLEAR_ATTACK, COMBO_ATTACK, MAX_COMBO, SPIN_ATTACK};
+
+/// What is known about an opponent. The search sends attack more eagerly when the
+/// opponent is close to topping out, and keeps the stack low when the opponent is likely
+/// to send a lot of garbage.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Opponent {
+    /// Height of the opponent's stack.
+    pub height: u32,
+    /// Number of garbage lines queued against the opponent.
+    pub garbage: u32,
+    /// Number of lines the opponent is likely to send with their next clear.
+    pub threat: u32,
+}
+
+impl Opponent {
+    /// Estimate the threat of an opponent from their matrix: the attack of the best line
+    /// clear that is already set up (a reachable T-spin slot, or rows that are full except
+    /// for the well), plus their combo and back-to-back bonuses.
+    pub fn new(matrix: &Mat, b2b: bool, combo: u32, garbage: u32) -> Self {
+        let heights = features::column_heights(matrix);
+        let well_rows = features::well(&heights).map_or(0, |(x, _)| {
+            let row = FULL & !(1 << x);
+            matrix.rows().iter().filter(|&&r| r == row).count()
+        });
+
+        let mut clear = CLEAR_ATTACK[well_rows.min(4)];
+        let mut b2b_clear = well_rows >= 4;
+        let spin = tspin::slots(matrix).iter().map(|slot| slot.cleared).max();
+        if let Some(cleared) = spin {
+            clear = clear.max(SPIN_ATTACK[cleared as usize]);
+            b2b_clear = true;
+        }
+
+        let mut threat = clear + COMBO_ATTACK[combo.min(MAX_COMBO as u32) as usize];
+        if b2b && b2b_clear {
+            threat += B2B_ATTACK;
+        }
+        Self {
+            height: features::max_height(&heights) as u32,
+            garbage,
+            threat: threat as u32,
+        }
+    }
+
+    /// Combine the models of several opponents into one, assuming the worst case: the
+    /// most vulnerable opponent that can be attacked, and the strongest threat.
+    pub fn combine(self, other: Self) -> Self {
+        let danger = |o: &Self| o.height + o.garbage;
+        let target = if danger(&other) > danger(&self) {
+            other
+        } else {
+            self
+        };
+        Self {
+            threat: self.threat.max(other.threat),
+            ..target
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mino::matrix::EMPTY;
+
+    #[test]
+    fn test_threat() {
+        let quad = [EMPTY | 0b1111111110; 4];
+        let opp = Opponent::new(Mat::new(&quad), true, 0, 2);
+        assert_eq!(
+            opp,
+            Opponent {
+                height: 4,
+                garbage: 2,
+                threat: 5,
+            }
+        );
+
+        let opp = Opponent::new(Mat::new(&quad[..2]), true, 3, 0);
+        assert_eq!(opp.threat, 1 + 1);
+
+        // a TSD slot, where the T has to be spun in under the overhang
+        let tsd = [
+            EMPTY | 0b1111101111,
+            EMPTY | 0b1111000111,
+            EMPTY | 0b0000001111,
+            EMPTY | 0b0000001000,
+        ];
+        let opp = Opponent::new(Mat::new(&tsd), true, 0, 0);
+        assert_eq!(opp.threat, 4 + 1);
+
+        let opp = Opponent::new(Mat::empty(), false, 0, 0);
+        assert_eq!(opp, Opponent::default());
+    }
+
+    #[test]
+    fn test_combine() {
+        let a = Opponent {
+            height: 10,
+            garbage: 0,
+            threat: 1,
+        };
+        let b = Opponent {
+            height: 4,
+            garbage: 2,
+            threat: 6,
+        };
+        assert_eq!(a.combine(b), Opponent { threat: 6, ..a });
+        assert_eq!(b.combine(a), Opponent { threat: 6, ..a });
+    }
+}
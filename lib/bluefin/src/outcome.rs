@@ -0,0 +1,48 @@
+//! Result of a search, incl. statistics that callers may want to record.
+
+use std::time::Duration;
+
+use crate::plan::Plan;
+
+/// Result of [`crate::bot`] or [`crate::Session::search`].
+#[derive(Clone)]
+pub struct Outcome {
+    /// The move to play, or the reason why there is none.
+    pub plan: Result<Plan, NoMove>,
+    pub stats: Stats,
+}
+
+/// Reason why the search did not find a move.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, thiserror::Error)]
+pub enum NoMove {
+    /// The current piece overlaps the matrix where it spawns, so the game is lost.
+    #[error("the spawn position is blocked")]
+    SpawnBlocked,
+    /// Neither the current piece nor the hold piece can be placed anywhere.
+    #[error("there are no reachable placements")]
+    NoPlacements,
+    /// The chosen placement could not be reached by any inputs. This indicates a bug,
+    /// since the search only considers placements that can be reached.
+    #[error("the chosen placement is unreachable")]
+    Unreachable,
+}
+
+/// Statistics of a single search.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Stats {
+    /// Number of nodes added to the tree by this search.
+    pub nodes_expanded: usize,
+    /// Number of children found in the transposition table instead of being added.
+    pub transpositions: usize,
+    /// Number of nodes in the tree, incl. those reused from previous searches.
+    pub tree_size: usize,
+    /// Approximate number of bytes allocated by the tree.
+    pub memory: usize,
+    /// Number of placements in the best line of play.
+    pub depth: usize,
+    /// Number of completed generations, and the one the best line was found in.
+    pub generations: u32,
+    pub best_generation: u32,
+    pub best_score: Option<i32>,
+    pub time: Duration,
+}
@@ -0,0 +1,73 @@
+//! Taking a perfect clear when one can be completed with the pieces in the queue.
+
+use mino::matrix::Mat;
+use mino::pc::perfect_clear;
+use mino::standard_rules::{FallingPiece, Piece, Queue};
+
+use crate::search::Limits;
+
+/// Number of positions searched before giving up on a perfect clear, even if the search
+/// limits only count generations. The solver takes tens of microseconds per position.
+const MAX_NODES: usize = 10_000;
+
+/// Returns placements that clear the whole matrix without the stack being taller than
+/// `max_height`, using the queue `pieces` (see [`crate::plan::simulate`] to play them).
+/// Gives up after [`MAX_NODES`] positions, or once the node or time limit of `limits` is
+/// reached, counting each position searched as a node.
+pub(crate) fn solve(
+    matrix: &Mat,
+    pieces: &[Piece],
+    max_height: i8,
+    limits: &Limits,
+) -> Option<Vec<FallingPiece>> {
+    let queue = Queue::from(pieces);
+    let stop = |n| n >= MAX_NODES || limits.exhausted(n);
+    let solution = perfect_clear(matrix, queue, max_height, stop)?;
+    debug!(pc = solution.len());
+    Some(solution)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::eval::EvalWeights;
+    use crate::plan::simulate;
+    use crate::state::State;
+    use mino::matrix::EMPTY;
+    use mino::standard_rules::Piece::*;
+    use mino::SpinRules;
+
+    #[test]
+    fn test_solve() {
+        let rows = [EMPTY | 0b0000111111, EMPTY | 0b0000111111];
+        let matrix = Mat::new(&rows);
+        let weights = EvalWeights::default();
+        let state = State::new(false, 0, 0);
+        let rules = SpinRules::default();
+        let limits = Limits::default();
+
+        // hold the T to place the two Os
+        let pieces = [T, O, O];
+        let solution = solve(matrix, &pieces, 4, &limits).unwrap();
+        let line = simulate(matrix, &pieces, true, state, &weights, rules, &solution).unwrap();
+        assert_eq!(line.len(), 2);
+        assert!(line[0].hold);
+        assert!(!line[1].hold);
+        assert_eq!(line[0].piece.piece, O);
+        assert_eq!(line[1].cleared, 2);
+
+        assert!(solve(matrix, &[T, S], 4, &limits).is_none());
+        // the solver runs out of nodes before finding the perfect clear
+        assert!(solve(matrix, &pieces, 4, &Limits::nodes(1)).is_none());
+    }
+
+    #[test]
+    fn test_solve_budget() {
+        // there is a perfect clear 6 rows high, but it takes far more positions to find
+        // than the solver is allowed, and the default limits have no node limit
+        let rows = [EMPTY | 0b0000001111];
+        let matrix = Mat::new(&rows);
+        let pieces = [S, Z, S, Z, O, S, Z, T, S, Z, O, S, Z, J, L, T];
+        assert!(solve(matrix, &pieces, 6, &Limits::default()).is_none());
+    }
+}
@@ -0,0 +1,248 @@
+//! The line of play found by the search, beyond just the move to play now.
+
+use mino::input::Input;
+use mino::matrix::{Mat, MatBuf};
+use mino::places::{reach, reach_rotation};
+use mino::spin::kicks;
+use mino::standard_rules::{FallingPiece, Piece, Queue};
+use mino::{Spin, SpinRules};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use crate::dag::{NodeId, Tree};
+use crate::eval::{self, EvalWeights};
+use crate::state::State;
+
+/// A single placement in a line of play found by the search.
+#[derive(Clone)]
+pub struct Step {
+    /// Whether the piece is taken from hold (or held to play the next piece).
+    pub hold: bool,
+    pub piece: FallingPiece,
+    pub cleared: u8,
+    pub spin: Spin,
+    /// Score of the position after the placement.
+    pub score: i32,
+    matrix: MatBuf,
+    spin_rules: SpinRules,
+}
+
+impl Step {
+    /// Returns the matrix before the piece was placed.
+    pub fn matrix(&self) -> &Mat {
+        &self.matrix
+    }
+
+    /// Returns the inputs needed to place the piece, starting from its spawn position. If
+    /// the spin depends on the last move (see [`SpinRules::rotates`]), they end with a
+    /// rotation that gives the same spin, so that the game scores it the way the search
+    /// did. Returns `None` if there is no such rotation, rather than inputs that lose the
+    /// spin.
+    pub fn inputs(&self) -> Option<Vec<Input>> {
+        let (matrix, piece) = (&self.matrix, self.piece);
+        if !self.spin.is_spin() || !self.spin_rules.rotates(piece.piece) {
+            return reach(matrix, piece);
+        }
+        kicks(matrix, &self.piece)
+            .filter(|&kick| self.spin_rules.spin(matrix, piece, Some(kick)) == self.spin)
+            .find_map(|kick| reach_rotation(matrix, piece, kick))
+    }
+}
+
+/// Result of a search: the move to play now, and the rest of the line of play that the
+/// search expects to follow it.
+#[derive(Clone)]
+pub struct Plan {
+    /// Whether to hold before placing the piece.
+    pub hold: bool,
+    /// Inputs needed to place the piece.
+    pub inputs: Vec<Input>,
+    /// Placements leading to the best position found, starting with the move to play now.
+    pub line: Vec<Step>,
+    /// Best lines starting with other moves, best first. They are compared the same way
+    /// as `line`, by their rollouts when sampling. Empty unless requested with
+    /// [`crate::Session::with_alternatives`].
+    pub alternatives: Vec<Vec<Step>>,
+}
+
+/// Returns the placements along `path`, which starts at a child of the root and goes
+/// through a child of each node (see [`Tree::path`]). `hold_empty` tells whether the hold
+/// slot is empty at the root, in which case the tree treats the current piece as the hold
+/// piece.
+pub(crate) fn line(tree: &Tree, path: &[NodeId], mut hold_empty: bool) -> Vec<Step> {
+    let parents = std::iter::once(tree.root()).chain(path.iter().copied());
+    parents
+        .zip(path)
+        .map(|(parent, &id)| {
+            let i = tree.children(parent).iter().position(|&c| c == id);
+            let mv = tree.moves(parent)[i.expect("path goes through a child of each node")];
+            let queue = tree.queue(parent);
+            let current = if hold_empty {
+                queue.hold()
+            } else {
+                queue.next().first().copied()
+            };
+            let hold = current.is_some_and(|pc| pc != mv.piece.piece);
+            hold_empty &= !hold;
+            let mut matrix = MatBuf::new();
+            matrix.copy_from(tree.matrix(parent));
+            Step {
+                hold,
+                piece: mv.piece,
+                cleared: mv.cleared,
+                spin: mv.spin,
+                score: tree.node(id).score(),
+                matrix,
+                spin_rules: tree.spin_rules(),
+            }
+        })
+        .collect()
+}
+
+/// Returns the steps of a line of play that was not found by the search, by placing
+/// `placements` in order, starting from `matrix` with the queue `pieces` (see
+/// [`line`]). Returns `None` if the placements do not follow the queue.
+pub(crate) fn simulate(
+    matrix: &Mat,
+    pieces: &[Piece],
+    mut hold_empty: bool,
+    mut state: State,
+    weights: &EvalWeights,
+    spin_rules: SpinRules,
+    placements: &[FallingPiece],
+) -> Option<Vec<Step>> {
+    let mut queue = Queue::from(pieces);
+    let mut matrix = {
+        let mut buf = MatBuf::new();
+        buf.copy_from(matrix);
+        buf
+    };
+    let mut line = Vec::with_capacity(placements.len());
+    for &piece in placements {
+        let current = if hold_empty {
+            queue.hold()
+        } else {
+            queue.next().first().copied()
+        };
+        let hold = current.is_some_and(|pc| pc != piece.piece);
+        hold_empty &= !hold;
+        (_, queue) = queue.pop().find(|&(pc, _)| pc == piece.piece)?;
+
+        let before = matrix.clone();
+        let cells = piece.cells();
+        matrix.place(cells);
+        let cleared = matrix.clear_lines(cells.bottom());
+        let spin = match cleared {
+            0 => Spin::None,
+            _ => spin_rules.detect(&before, piece),
+        };
+        state = state.next(cleared, spin);
+        line.push(Step {
+            hold,
+            piece,
+            cleared,
+            spin,
+            score: eval::evaluate(weights, &matrix, state),
+            matrix: before,
+            spin_rules,
+        });
+    }
+    Some(line)
+}
+
+/// Returns the paths to the best leaves that start with a different move than `best`
+/// and from each other, best first. Leaves are compared by the `values` the search
+/// compared them by (see [`crate::search::SearchResult::values`]), so with sampling they
+/// are ranked by their rollouts like `best` was. Nodes that were reached from more than
+/// one parent are followed from each of them, so a line may go through a parent other
+/// than the one the node was first reached from.
+pub(crate) fn alternatives(
+    tree: &Tree,
+    best: NodeId,
+    values: &HashMap<NodeId, i32>,
+    count: usize,
+) -> Vec<Vec<NodeId>> {
+    if count == 0 {
+        return Vec::new();
+    }
+    let best_move = tree.path(best).first().copied();
+
+    let mut below = vec![None; tree.len()];
+    let mut lines = Vec::new();
+    for &child in tree.children(tree.root()) {
+        if Some(child) == best_move {
+            continue;
+        }
+        let Some(leaf @ (_, Reverse(end))) = best_leaf(tree, values, child, &mut below) else {
+            continue;
+        };
+        // follow the children that lead to the leaf
+        let mut path = vec![child];
+        let mut id = child;
+        while id != end {
+            id = *tree
+                .children(id)
+                .iter()
+                .find(|c| below[c.index()] == Some(Some(leaf)))
+                .expect("a child leads to the best leaf below its parent");
+            path.push(id);
+        }
+        lines.push((leaf, path));
+    }
+
+    lines.sort_by_key(|&(leaf, _)| Reverse(leaf));
+    lines.truncate(count);
+    lines.into_iter().map(|(_, path)| path).collect()
+}
+
+// leaves compare by value, then by id so that ties go to the leaf found first
+type Leaf = (i32, Reverse<NodeId>);
+
+// returns the best leaf below `id`, through any of the parents of each node. `below`
+// caches the result for every node that was visited
+fn best_leaf(
+    tree: &Tree,
+    values: &HashMap<NodeId, i32>,
+    id: NodeId,
+    below: &mut [Option<Option<Leaf>>],
+) -> Option<Leaf> {
+    if let Some(leaf) = below[id.index()] {
+        return leaf;
+    }
+    let mut leaf = values.get(&id).map(|&value| (value, Reverse(id)));
+    for &child in tree.children(id) {
+        leaf = leaf.max(best_leaf(tree, values, child, below));
+    }
+    below[id.index()] = Some(leaf);
+    leaf
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::search::{search, Limits};
+    use mino::standard_rules::Piece::*;
+
+    #[test]
+    fn test_alternatives_use_rollouts() {
+        let mut tree = Tree::new(Mat::empty(), &[T, I, O], State::default());
+        let samples = [vec![L, J, S], vec![Z, S, J], vec![O, T, L]];
+        let result = search(&mut tree, &Limits::nodes(500), 1, &samples);
+        let values = &result.values;
+        // otherwise this would not tell rollouts and scores apart
+        assert!(values.iter().any(|(&id, &v)| v != tree.node(id).score()));
+
+        let lines = alternatives(&tree, result.best, values, 5);
+        assert_eq!(lines.len(), 5);
+        let mut prev = values[&result.best];
+        for path in &lines {
+            assert!(tree.children(tree.root()).contains(&path[0]));
+            for pair in path.windows(2) {
+                assert!(tree.children(pair[0]).contains(&pair[1]));
+            }
+            let value = values[path.last().unwrap()];
+            assert!(value <= prev);
+            prev = value;
+        }
+    }
+}
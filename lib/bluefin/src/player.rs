@@ -0,0 +1,255 @@
+//! Implementation of [`botris::Bot`] using bluefin.
+
+use botris::convert::commands;
+use botris::{Bot, Command, GameState, SpinRules};
+use mino::standard_rules::Piece;
+use mino::MatBuf;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::{
+    Book, Context, EvalWeights, Explanation, Limits, Opponent, Plan, Sampling, Session, Stats,
+};
+
+/// Fraction of the time between moves that is spent searching; the rest is left as
+/// margin for latency.
+const THINK_TIME_FRACTION: f32 = 0.75;
+
+/// Bot that plays Botris games using a [`Session`] to search for moves.
+#[derive(Default)]
+pub struct Bluefin {
+    session: Session,
+    limits: Limits,
+    think_time: Option<Duration>,
+    plan: Option<Plan>,
+    stats: Option<Stats>,
+    // latest model of each opponent, by id
+    opponents: HashMap<String, Opponent>,
+}
+
+impl Bluefin {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the limits used for each search.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Set the number of threads used by the search.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.session.set_threads(threads);
+        self
+    }
+
+    /// Set the number of alternative lines to include in each plan, see
+    /// [`Bluefin::plan`].
+    pub fn with_alternatives(mut self, alternatives: usize) -> Self {
+        self.session.set_alternatives(alternatives);
+        self
+    }
+
+    /// Look past the end of the queue by sampling pieces, see [`Sampling`].
+    pub fn with_sampling(mut self, sampling: Sampling) -> Self {
+        self.session.set_sampling(Some(sampling));
+        self
+    }
+
+    /// Take perfect clears up to `max_height` rows tall when the queue allows one, see
+    /// [`Session::with_perfect_clear`].
+    pub fn with_perfect_clear(mut self, max_height: i8) -> Self {
+        self.session.set_perfect_clear(Some(max_height));
+        self
+    }
+
+    /// Build an opener from the book at the start of each round, see [`Session::with_book`].
+    pub fn with_book(mut self, book: Book) -> Self {
+        self.session.set_book(book);
+        self
+    }
+
+    /// Set the weights of the evaluation function.
+    pub fn with_weights(mut self, weights: EvalWeights) -> Self {
+        self.session.set_weights(weights);
+        self
+    }
+
+    /// Set the spin rules of the games being played, see [`Session::with_spin_rules`].
+    pub fn with_spin_rules(mut self, spin_rules: SpinRules) -> Self {
+        self.session.set_spin_rules(spin_rules.into());
+        self
+    }
+
+    /// Set the amount of time to search for each move. This overrides the deadline of the
+    /// search limits, and removes the limit on number of generations.
+    pub fn with_think_time(mut self, think_time: Duration) -> Self {
+        self.set_think_time(think_time);
+        self
+    }
+
+    pub fn set_think_time(&mut self, think_time: Duration) {
+        self.think_time = Some(think_time);
+        self.limits.max_generations = None;
+    }
+
+    /// Set the think time according to the rate at which moves will be requested. A rate
+    /// that is not positive (e.g. sent by a misconfigured server) is ignored.
+    pub fn set_pps(&mut self, pps: f32) {
+        if pps.is_nan() || pps <= 0.0 {
+            warn!(pps, "ignoring invalid pps");
+            return;
+        }
+        self.set_think_time(Duration::from_secs_f32(THINK_TIME_FRACTION / pps));
+    }
+
+    /// Returns the plan found for the last move, which includes the placements the bot
+    /// expects to make after it.
+    pub fn plan(&self) -> Option<&Plan> {
+        self.plan.as_ref()
+    }
+
+    /// Returns the statistics of the last search.
+    pub fn stats(&self) -> Option<&Stats> {
+        self.stats.as_ref()
+    }
+
+    /// Explain the last move, see [`Session::explain`].
+    pub fn explain(&self) -> Option<Explanation> {
+        self.session.explain()
+    }
+}
+
+impl Bot for Bluefin {
+    fn request_move(&mut self, game_state: &GameState) -> Option<Vec<Command>> {
+        let mut limits = self.limits;
+        if let Some(think_time) = self.think_time {
+            limits.deadline = Some(Instant::now() + think_time);
+        }
+
+        let current = game_state.current.piece.into();
+        let hold = game_state.held.map(Piece::from);
+        let queue = game_state
+            .queue
+            .iter()
+            .map(|&x| x.into())
+            .collect::<Vec<Piece>>();
+        let matrix = MatBuf::from(&game_state.board);
+        let ctx = Context {
+            opponent: self.opponents.values().copied().reduce(Opponent::combine),
+            ..Context::from(game_state)
+        };
+
+        let outcome = self
+            .session
+            .search(current, &queue, hold, &matrix, ctx, &limits);
+        self.stats = Some(outcome.stats);
+        self.plan = outcome
+            .plan
+            .inspect_err(|reason| warn!("no move: {reason}"))
+            .ok();
+        self.plan
+            .as_ref()
+            .and_then(|plan| commands(plan.hold, &plan.inputs))
+    }
+
+    fn new_round(&mut self) {
+        self.session.reset();
+        self.plan = None;
+        self.stats = None;
+        self.opponents.clear();
+    }
+
+    fn opponent_update(&mut self, opponent: &str, game_state: &GameState) {
+        if game_state.dead {
+            self.opponents.remove(opponent);
+        } else {
+            let model = Opponent::from(game_state);
+            trace!(opponent, ?model);
+            self.opponents.insert(opponent.to_owned(), model);
+        }
+    }
+}
+
+impl From<&GameState> for Context {
+    fn from(game_state: &GameState) -> Self {
+        Self {
+            b2b: game_state.b2b,
+            combo: game_state.combo,
+            garbage: game_state.garbage_queued.len() as u32,
+            opponent: None,
+        }
+    }
+}
+
+impl From<&GameState> for Opponent {
+    fn from(game_state: &GameState) -> Self {
+        Opponent::new(
+            &MatBuf::from(&game_state.board),
+            game_state.b2b,
+            game_state.combo,
+            game_state.garbage_queued.len() as u32,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::plan;
+    use crate::state::State;
+    use mino::input::Rot;
+    use mino::places::reach;
+    use mino::standard_rules::FallingPiece;
+    use mino::Spin;
+
+    #[test]
+    fn test_set_pps() {
+        let mut bot = Bluefin::new();
+        bot.set_pps(2.0);
+        assert_eq!(bot.think_time, Some(Duration::from_secs_f32(0.375)));
+        for pps in [0.0, -1.0, f32::NAN] {
+            bot.set_pps(pps);
+            assert_eq!(bot.think_time, Some(Duration::from_secs_f32(0.375)));
+        }
+        bot.set_pps(f32::INFINITY);
+        assert_eq!(bot.think_time, Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_spin_matches_game() {
+        // 3 x.........
+        // 2 xxxx.x....
+        // 1 xxxx.x...x
+        // 0 xxxxxx.xxx
+        let mut mat = MatBuf::new();
+        mat.set(0, 0b1110111111);
+        mat.set(1, 0b1000101111);
+        mat.set(2, 0b0000101111);
+        mat.set(3, 0b0000000001);
+        let target = FallingPiece::new(Piece::T, (5, 2, Rot::E));
+        let rules = SpinRules::TSpin;
+        let weights = EvalWeights::default();
+        let line = plan::simulate(
+            &mat,
+            &[Piece::T],
+            false,
+            State::default(),
+            &weights,
+            rules.into(),
+            &[target],
+        );
+        let step = &line.unwrap()[0];
+        assert_eq!(step.spin, Spin::Mini);
+        // the shortest path rotates in the air before dropping, which is not a spin
+        assert_ne!(reach(&mat, target), step.inputs());
+
+        let mut game = botris::Game::new_seeded(0).with_spin_rules(rules);
+        game.state.board = botris::Board::from(&*mat);
+        game.state.current = botris::PieceData::spawn(botris::Piece::T);
+        let outcome = game.perform_commands(&commands(false, &step.inputs().unwrap()).unwrap());
+        assert_eq!(outcome.cleared, 1);
+        assert_eq!(Spin::from(outcome.spin), step.spin);
+    }
+}
@@ -0,0 +1,351 @@
+use mino::standard_rules::Piece;
+use std::collections::HashMap;
+use std::mem::swap;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::dag::{Expansion, NodeId, Tree};
+
+const INITIAL_BEAM_CAPACITY: usize = 32 * 1024 * 1024;
+const INITIAL_BEAM_WIDTH_LOG2: u32 = 4;
+const MAX_BEAM_WIDTH_LOG2: u32 = 20;
+/// Minimum number of nodes to expand per thread; spawning threads for fewer nodes than
+/// this costs more than it saves.
+const MIN_NODES_PER_THREAD: usize = 64;
+/// Minimum number of leaves to roll out per thread; rollouts are much slower than
+/// expanding a node.
+const MIN_LEAVES_PER_THREAD: usize = 4;
+
+/// Settings for looking past the end of the queue by sampling the pieces that may come
+/// next. Each leaf of the search is scored by the average of its rollouts, in which the
+/// sampled pieces are placed greedily.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Sampling {
+    /// Number of sequences of pieces to sample.
+    pub samples: usize,
+    /// Number of pieces in each sequence. The last piece ends up in hold, so this many
+    /// pieces minus one are placed.
+    pub depth: usize,
+}
+
+/// Limits on how much work the search may do. The search keeps running generations with
+/// increasing beam width until one of the limits is reached, and then returns the best
+/// result found so far. The first generation is always completed, so that there is a
+/// result to return.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Limits {
+    /// Stop searching at this time.
+    pub deadline: Option<Instant>,
+    /// Stop searching after this many nodes have been expanded.
+    pub max_nodes: Option<usize>,
+    /// Stop searching after this many generations.
+    pub max_generations: Option<u32>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            deadline: None,
+            max_nodes: None,
+            max_generations: Some(5),
+        }
+    }
+}
+
+impl Limits {
+    /// No limits except for the time `think_time` from now.
+    pub fn time(think_time: Duration) -> Self {
+        Self {
+            deadline: Some(Instant::now() + think_time),
+            max_nodes: None,
+            max_generations: None,
+        }
+    }
+
+    /// No limits except for the number of nodes expanded.
+    pub fn nodes(max_nodes: usize) -> Self {
+        Self {
+            deadline: None,
+            max_nodes: Some(max_nodes),
+            max_generations: None,
+        }
+    }
+
+    fn reached(&self, generation: u32, total_expanded: usize) -> bool {
+        self.max_generations.is_some_and(|n| generation >= n) || self.exhausted(total_expanded)
+    }
+
+    // whether the node or time limit is reached, for work that is not done in generations
+    pub(crate) fn exhausted(&self, nodes: usize) -> bool {
+        self.max_nodes.is_some_and(|n| nodes >= n)
+            || self.deadline.is_some_and(|t| Instant::now() >= t)
+    }
+}
+
+/// Result of the search.
+pub struct SearchResult {
+    pub best: NodeId,
+    pub total_expanded: usize,
+    /// Number of children that were found in the transposition table instead of being
+    /// added to the tree again.
+    pub transpositions: usize,
+    pub best_generation: u32,
+    pub generations: u32,
+    /// Value of every leaf that was compared to find `best`: its score, or the average of
+    /// its rollouts if there were samples.
+    pub values: HashMap<NodeId, i32>,
+}
+
+/// Beam search starting from the root of `tree`. Each generation searches to the end of
+/// the queue with a beam twice as wide as the previous one, reusing the nodes that have
+/// already been expanded, incl. those left in the tree by previous searches.
+///
+/// If `samples` is not empty, leaves are compared by their rollouts with each of the
+/// sampled sequences of pieces, see [`Sampling`].
+///
+/// Nodes are expanded using up to `threads` threads. The children are added to the tree
+/// in the same order regardless of the number of threads, so the result only depends on
+/// the limits, not on how many threads were used (except for the number of nodes that
+/// can be expanded before a deadline).
+pub fn search(
+    tree: &mut Tree,
+    limits: &Limits,
+    threads: usize,
+    samples: &[Vec<Piece>],
+) -> SearchResult {
+    let mut beam = Vec::with_capacity(INITIAL_BEAM_CAPACITY);
+    let mut next_beam = Vec::with_capacity(INITIAL_BEAM_CAPACITY);
+    let mut unexpanded = Vec::new();
+
+    let root = tree.root();
+    let mut total_expanded = 1;
+    let initial_transpositions = tree.transpositions();
+
+    let mut best = root;
+    let mut best_value = i32::MIN;
+    let mut values = HashMap::new();
+    let mut best_generation = 0;
+    let mut generation = 0;
+
+    'search: loop {
+        if generation > 0 && limits.reached(generation, total_expanded) {
+            break;
+        }
+
+        let beam_width_log2 = (INITIAL_BEAM_WIDTH_LOG2 + generation).min(MAX_BEAM_WIDTH_LOG2);
+        let beam_width = 1 << beam_width_log2; // 16, 32, 64, 128, 256, ...
+        trace!(generation, beam_width, total_expanded);
+        beam.clear();
+        beam.push(root);
+
+        let mut truncated = false;
+
+        loop {
+            if generation > 0 && limits.reached(generation, total_expanded) {
+                // abandon this generation since its leaves are not complete
+                break 'search;
+            }
+
+            if beam.len() > beam_width {
+                beam.select_nth_unstable_by_key(beam_width, |&n| tree.node(n).ord_min_best());
+                beam.truncate(beam_width);
+                truncated = true;
+            }
+
+            unexpanded.clear();
+            unexpanded.extend(beam.iter().filter(|&&n| !tree.node(n).is_expanded()));
+            for expansion in generate(tree, &unexpanded, threads) {
+                total_expanded += tree.insert(expansion);
+            }
+
+            next_beam.clear();
+            for &node in beam.iter() {
+                next_beam.extend_from_slice(tree.children(node));
+            }
+            // nodes reached from more than one parent only need to be in the beam once
+            next_beam.sort_unstable();
+            next_beam.dedup();
+
+            if next_beam.is_empty() {
+                let leaf_values = leaf_values(tree, &beam, samples, &mut values, threads);
+                for (&node, value) in beam.iter().zip(leaf_values) {
+                    if value > best_value {
+                        best = node;
+                        best_value = value;
+                        best_generation = generation;
+                        trace!(new_best = value, generation, total_expanded);
+                    }
+                }
+                break;
+            }
+
+            swap(&mut beam, &mut next_beam);
+        }
+
+        generation += 1;
+
+        if !truncated {
+            // the beam is wider than the tree, so there is nothing new to discover
+            break;
+        }
+    }
+
+    SearchResult {
+        best,
+        total_expanded,
+        transpositions: tree.transpositions() - initial_transpositions,
+        best_generation,
+        generations: generation,
+        values,
+    }
+}
+
+// expands the nodes in parallel, returning the expansions in the same order as `nodes`
+fn generate(tree: &Tree, nodes: &[NodeId], threads: usize) -> Vec<Expansion> {
+    parallel_map(nodes, threads, MIN_NODES_PER_THREAD, |n| tree.generate(n))
+}
+
+// returns the value of each leaf: its score, or the average of its rollouts if there are
+// samples. values are cached in `values`, since the leaves of each generation overlap
+fn leaf_values(
+    tree: &Tree,
+    leaves: &[NodeId],
+    samples: &[Vec<Piece>],
+    values: &mut HashMap<NodeId, i32>,
+    threads: usize,
+) -> Vec<i32> {
+    if samples.is_empty() {
+        values.extend(leaves.iter().map(|&n| (n, tree.node(n).score())));
+        return leaves.iter().map(|&n| tree.node(n).score()).collect();
+    }
+    let new = leaves
+        .iter()
+        .copied()
+        .filter(|n| !values.contains_key(n))
+        .collect::<Vec<_>>();
+    let rollouts = parallel_map(&new, threads, MIN_LEAVES_PER_THREAD, |n| {
+        let total = samples
+            .iter()
+            .map(|pieces| tree.rollout(n, pieces) as i64)
+            .sum::<i64>();
+        (total / samples.len() as i64) as i32
+    });
+    values.extend(new.into_iter().zip(rollouts));
+    leaves.iter().map(|n| values[n]).collect()
+}
+
+// applies `f` to every node, using up to `threads` threads with at least `min_per_thread`
+// nodes each, and returns the results in the same order as `nodes`
+fn parallel_map<T: Send>(
+    nodes: &[NodeId],
+    threads: usize,
+    min_per_thread: usize,
+    f: impl Fn(NodeId) -> T + Sync,
+) -> Vec<T> {
+    let threads = threads.min(nodes.len() / min_per_thread);
+    if threads <= 1 {
+        return nodes.iter().map(|&n| f(n)).collect();
+    }
+
+    let chunk_size = nodes.len().div_ceil(threads);
+    let f = &f;
+    thread::scope(|s| {
+        let handles = nodes
+            .chunks(chunk_size)
+            .map(|chunk| s.spawn(move || chunk.iter().map(|&n| f(n)).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("search thread panicked"))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::State;
+    use mino::standard_rules::Piece::*;
+    use mino::Mat;
+
+    #[test]
+    fn test_first_generation_always_completes() {
+        let mut tree = Tree::new(Mat::empty(), &[T, I, O, L], State::default());
+        let result = search(&mut tree, &Limits::nodes(1), 1, &[]);
+        assert_eq!(result.generations, 1);
+        assert!(tree.original_piece(result.best).is_some());
+    }
+
+    #[test]
+    fn test_node_limit() {
+        let queue = [T, I, O, L, J, S];
+        let mut tree = Tree::new(Mat::empty(), &queue, State::default());
+        let small = search(&mut tree, &Limits::nodes(6_000), 1, &[]);
+        let small_score = tree.node(small.best).score();
+        let mut tree = Tree::new(Mat::empty(), &queue, State::default());
+        let large = search(&mut tree, &Limits::nodes(100_000), 1, &[]);
+        assert!(small.generations < large.generations);
+        assert!(tree.node(large.best).score() >= small_score);
+    }
+
+    #[test]
+    fn test_exhausted_tree() {
+        let mut tree = Tree::new(Mat::empty(), &[O], State::default());
+        let result = search(&mut tree, &Limits::nodes(usize::MAX), 1, &[]);
+        // the root is expanded once, and the search stops at the first generation whose
+        // beam is wide enough to hold every position; an O piece has one per column
+        let n_places = 9;
+        assert_eq!(result.total_expanded, 1 + n_places);
+        assert_eq!(tree.len(), 1 + n_places);
+        let generations = (n_places as u32).next_power_of_two().ilog2() - INITIAL_BEAM_WIDTH_LOG2;
+        assert_eq!(result.generations, generations + 1);
+    }
+
+    #[test]
+    fn test_search_again() {
+        let mut tree = Tree::new(Mat::empty(), &[T, I, O, L], State::default());
+        let first = search(&mut tree, &Limits::default(), 1, &[]);
+        let len = tree.len();
+        // a second search with the same limits finds the same result without expanding
+        // anything new
+        let second = search(&mut tree, &Limits::default(), 1, &[]);
+        assert_eq!(tree.len(), len);
+        assert_eq!(second.total_expanded, 1);
+        assert_eq!(first.best, second.best);
+    }
+
+    #[test]
+    fn test_threads_deterministic() {
+        let queue = [T, I, O, L, J, S];
+        let limits = Limits::nodes(50_000);
+        let mut tree = Tree::new(Mat::empty(), &queue, State::default());
+        let single = search(&mut tree, &limits, 1, &[]);
+        let single_len = tree.len();
+        for threads in [2, 3, 8] {
+            let mut tree = Tree::new(Mat::empty(), &queue, State::default());
+            let multi = search(&mut tree, &limits, threads, &[]);
+            assert_eq!(tree.len(), single_len);
+            assert_eq!(multi.best, single.best);
+            assert_eq!(multi.total_expanded, single.total_expanded);
+            assert_eq!(multi.generations, single.generations);
+        }
+    }
+
+    #[test]
+    fn test_sampling() {
+        let queue = [T, I, O];
+        let samples = [vec![L, J, S], vec![Z, S, J], vec![O, T, L]];
+        let limits = Limits {
+            max_generations: Some(2),
+            ..Limits::default()
+        };
+        let mut tree = Tree::new(Mat::empty(), &queue, State::default());
+        let single = search(&mut tree, &limits, 1, &samples);
+        assert!(tree.queue(single.best).hold().is_none());
+        for threads in [2, 8] {
+            let mut tree = Tree::new(Mat::empty(), &queue, State::default());
+            let multi = search(&mut tree, &limits, threads, &samples);
+            assert_eq!(multi.best, single.best);
+        }
+    }
+}
@@ -0,0 +1,592 @@
+use mino::matrix::{Mat, EMPTY};
+use mino::standard_rules::{FallingPiece, Piece, Queue};
+use mino::SpinRules;
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+use std::mem;
+use std::time::Instant;
+
+use crate::bag::History;
+use crate::dag::{NodeId, Tree};
+use crate::eval::EvalWeights;
+use crate::explain::Explanation;
+use crate::opener::{self, Book, Opener};
+use crate::outcome::{NoMove, Outcome, Stats};
+use crate::pc;
+use crate::plan::{self, Plan, Step};
+use crate::search::{self, Limits, Sampling, SearchResult};
+use crate::state::State;
+use crate::Context;
+
+/// Keeps the search tree between moves. If the position given to the next search is one
+/// that the tree predicted, the subtree under that position is reused instead of being
+/// searched again from scratch.
+pub struct Session {
+    tree: Option<Tree>,
+    // best node found by the last search, and whether the hold was empty
+    best: Option<(NodeId, bool)>,
+    threads: usize,
+    alternatives: usize,
+    sampling: Option<Sampling>,
+    // maximum height of perfect clears to look for
+    perfect_clear: Option<i8>,
+    book: Book,
+    // opener being built, chosen at the start of the round
+    opener: Option<Opener>,
+    round_start: bool,
+    // pieces seen so far, to infer the state of the bag when sampling
+    history: History,
+    rng: SmallRng,
+    weights: EvalWeights,
+    spin_rules: SpinRules,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            tree: None,
+            best: None,
+            threads: 1,
+            alternatives: 0,
+            sampling: None,
+            perfect_clear: None,
+            book: Book::default(),
+            opener: None,
+            round_start: true,
+            history: History::default(),
+            rng: SmallRng::seed_from_u64(0),
+            weights: EvalWeights::default(),
+            spin_rules: SpinRules::default(),
+        }
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the number of threads used to expand nodes. The moves chosen do not depend on
+    /// the number of threads, except that more nodes can be searched before a deadline.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.set_threads(threads);
+        self
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    /// Set the number of alternative lines of play to include in each [`Plan`], besides
+    /// the best one.
+    pub fn with_alternatives(mut self, alternatives: usize) -> Self {
+        self.set_alternatives(alternatives);
+        self
+    }
+
+    pub fn set_alternatives(&mut self, alternatives: usize) {
+        self.alternatives = alternatives;
+    }
+
+    /// Look past the end of the queue by sampling the pieces that may come next, see
+    /// [`Sampling`]. Disabled by default.
+    pub fn with_sampling(mut self, sampling: Sampling) -> Self {
+        self.set_sampling(Some(sampling));
+        self
+    }
+
+    pub fn set_sampling(&mut self, sampling: Option<Sampling>) {
+        if sampling != self.sampling {
+            // the best leaves were chosen with the old samples
+            self.reset();
+        }
+        self.sampling = sampling;
+    }
+
+    /// Before searching, look for a perfect clear that can be completed with the known
+    /// pieces without the stack being taller than `max_height`, and take it if there is
+    /// one. Disabled by default, since the search for a perfect clear can be slow on
+    /// low stacks with a long queue. It gives up after a fixed number of positions, and
+    /// shares the node and time limits of the search; if they run out first, the search
+    /// still completes its first generation.
+    pub fn with_perfect_clear(mut self, max_height: i8) -> Self {
+        self.set_perfect_clear(Some(max_height));
+        self
+    }
+
+    pub fn set_perfect_clear(&mut self, max_height: Option<i8>) {
+        self.perfect_clear = max_height;
+    }
+
+    /// At the start of each round, pick the first opener of the book that is viable with
+    /// the queue, and build it before searching. See [`Book`].
+    pub fn with_book(mut self, book: Book) -> Self {
+        self.set_book(book);
+        self
+    }
+
+    pub fn set_book(&mut self, book: Book) {
+        self.book = book;
+    }
+
+    /// Set the weights of the evaluation function.
+    pub fn with_weights(mut self, weights: EvalWeights) -> Self {
+        self.set_weights(weights);
+        self
+    }
+
+    pub fn set_weights(&mut self, weights: EvalWeights) {
+        if weights != self.weights {
+            // the scores in the tree were computed using the old weights
+            self.reset();
+        }
+        self.weights = weights;
+    }
+
+    pub fn weights(&self) -> &EvalWeights {
+        &self.weights
+    }
+
+    /// Set the rules deciding which placements are spins, which should match the rules
+    /// of the game being played. Defaults to [`SpinRules::AllSpin`].
+    pub fn with_spin_rules(mut self, spin_rules: SpinRules) -> Self {
+        self.set_spin_rules(spin_rules);
+        self
+    }
+
+    pub fn set_spin_rules(&mut self, spin_rules: SpinRules) {
+        if spin_rules != self.spin_rules {
+            // the states in the tree were computed using the old rules
+            self.reset();
+        }
+        self.spin_rules = spin_rules;
+    }
+
+    /// Forget the current tree, e.g. because a new game has started.
+    pub fn reset(&mut self) {
+        self.tree = None;
+        self.best = None;
+        self.history = History::default();
+        self.opener = None;
+        self.round_start = true;
+        self.rng = SmallRng::seed_from_u64(0);
+    }
+
+    /// Explain the move chosen by the last search: the line of play that it expects to
+    /// follow, and the evaluation of the position at the end of it. Returns `None` if the
+    /// last move was part of an opener or a perfect clear, which are taken without
+    /// searching.
+    pub fn explain(&self) -> Option<Explanation> {
+        let tree = self.tree.as_ref()?;
+        let (best, hold_empty) = self.best?;
+        Some(Explanation::new(tree, best, hold_empty))
+    }
+
+    /// Search for the best move, and return it along with the line of play that is
+    /// expected to follow it. See [`crate::bot`].
+    pub fn search(
+        &mut self,
+        current: Piece,
+        queue: &[Piece],
+        hold: Option<Piece>,
+        matrix: &Mat,
+        ctx: Context,
+        limits: &Limits,
+    ) -> Outcome {
+        let start = Instant::now();
+        self.best = None;
+
+        if FallingPiece::spawn(current).cells().collides(matrix) {
+            return Outcome {
+                plan: Err(NoMove::SpawnBlocked),
+                stats: Stats::default(),
+            };
+        }
+
+        let pieces: Vec<Piece> = [hold.as_slice(), &[current], queue]
+            .into_iter()
+            .flat_map(|x| x.iter().copied())
+            .collect();
+
+        debug!("start {}", Queue::from(&*pieces));
+        trace!(?ctx);
+
+        let upcoming = &pieces[hold.is_some() as usize..];
+        self.history.observe(upcoming);
+        let samples = match self.sampling {
+            Some(Sampling { samples, depth }) => (0..samples)
+                .map(|_| self.history.sample(&mut self.rng, depth))
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let state = State::new(ctx.b2b, ctx.combo, ctx.garbage).with_opponent(ctx.opponent);
+        let hold_empty = hold.is_none();
+        if let Some(line) = self.fixed_line(matrix, &pieces, hold_empty, state, limits) {
+            let stats = Stats {
+                depth: line.len(),
+                best_score: line.last().map(|step| step.score),
+                time: start.elapsed(),
+                ..Stats::default()
+            };
+            let first = line[0].piece;
+            let plan = line[0]
+                .inputs()
+                .ok_or(NoMove::Unreachable)
+                .map(|inputs| Plan {
+                    hold: first.piece != current,
+                    inputs,
+                    line,
+                    alternatives: Vec::new(),
+                });
+            return Outcome { plan, stats };
+        }
+
+        let tree = match self.tree.take() {
+            Some(mut tree) => match find_child(&tree, matrix, &pieces) {
+                Some((child, revealed)) => {
+                    tree.reroot(child, state);
+                    tree.extend_queue(&pieces[pieces.len() - revealed..]);
+                    debug!(reused = tree.len());
+                    tree
+                }
+                None => self.new_tree(matrix, &pieces, state),
+            },
+            None => self.new_tree(matrix, &pieces, state),
+        };
+        let tree = self.tree.insert(tree);
+
+        let SearchResult {
+            best,
+            total_expanded,
+            transpositions,
+            best_generation,
+            generations,
+            values,
+        } = search::search(tree, limits, self.threads, &samples);
+
+        let depth = tree.path(best).len();
+        let stats = Stats {
+            nodes_expanded: total_expanded,
+            transpositions,
+            tree_size: tree.len(),
+            memory: tree.memory_usage(),
+            depth,
+            generations,
+            best_generation,
+            best_score: (depth > 0).then(|| tree.node(best).score()),
+            time: start.elapsed(),
+        };
+        debug!(best = tree.node(best).score(), state = ?tree.node(best).state());
+        debug!(?stats);
+
+        let alternatives = plan::alternatives(tree, best, &values, self.alternatives);
+        let plan = make_plan(tree, best, current, hold_empty, alternatives);
+        if plan.is_ok() {
+            self.best = Some((best, hold_empty));
+        }
+        Outcome { plan, stats }
+    }
+}
+
+impl Session {
+    // line of play that is taken without searching: continuing the opener, or a perfect
+    // clear
+    fn fixed_line(
+        &mut self,
+        matrix: &Mat,
+        pieces: &[Piece],
+        hold_empty: bool,
+        state: State,
+        limits: &Limits,
+    ) -> Option<Vec<Step>> {
+        if mem::take(&mut self.round_start) {
+            self.opener = self.book.find(matrix, Queue::from(pieces)).cloned();
+            debug!(opener = self.opener.as_ref().map(Opener::name));
+        }
+        if let Some(opener) = &self.opener {
+            let (weights, rules) = (&self.weights, self.spin_rules);
+            match opener::line(opener, matrix, pieces, hold_empty, state, weights, rules) {
+                Some(line) => return Some(line),
+                None => {
+                    debug!("opener finished");
+                    self.opener = None;
+                }
+            }
+        }
+        let max_height = self.perfect_clear?;
+        let solution = pc::solve(matrix, pieces, max_height, limits)?;
+        let (weights, rules) = (&self.weights, self.spin_rules);
+        plan::simulate(matrix, pieces, hold_empty, state, weights, rules, &solution)
+    }
+
+    fn new_tree(&self, matrix: &Mat, pieces: &[Piece], state: State) -> Tree {
+        Tree::new(matrix, pieces, state)
+            .with_weights(self.weights)
+            .with_spin_rules(self.spin_rules)
+    }
+}
+
+fn make_plan(
+    tree: &Tree,
+    best: NodeId,
+    current: Piece,
+    hold_empty: bool,
+    alternatives: Vec<Vec<NodeId>>,
+) -> Result<Plan, NoMove> {
+    let target = tree.original_piece(best).ok_or(NoMove::NoPlacements)?;
+    debug!(?target);
+    let line = plan::line(tree, &tree.path(best), hold_empty);
+    let inputs = line[0].inputs().ok_or(NoMove::Unreachable)?;
+    Ok(Plan {
+        hold: target.piece != current,
+        inputs,
+        line,
+        alternatives: alternatives
+            .into_iter()
+            .map(|path| plan::line(tree, &path, hold_empty))
+            .collect(),
+    })
+}
+
+/// Find the child of the root whose position matches `matrix` and the queue `pieces`,
+/// and return it along with the number of pieces at the end of `pieces` that were not
+/// known when the tree was built.
+fn find_child(tree: &Tree, matrix: &Mat, pieces: &[Piece]) -> Option<(NodeId, usize)> {
+    let new_queue = Queue::from(pieces);
+    tree.children(tree.root()).iter().find_map(|&child| {
+        if !same_matrix(tree.matrix(child), matrix) {
+            return None;
+        }
+        let queue = tree.queue(child);
+        let known = queue.next();
+        let matches = queue.hold() == new_queue.hold()
+            && new_queue.next().len() >= known.len()
+            && new_queue.next().starts_with(known);
+        matches.then(|| (child, new_queue.next().len() - known.len()))
+    })
+}
+
+// ignores empty rows at the top, since the matrix given by the game may contain them
+fn same_matrix(a: &Mat, b: &Mat) -> bool {
+    fn trim(mat: &Mat) -> &[u16] {
+        let rows = mat.rows();
+        let len = rows.iter().rposition(|&r| r != EMPTY).map_or(0, |y| y + 1);
+        &rows[..len]
+    }
+    trim(a) == trim(b)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mino::standard_rules::Piece::*;
+    use mino::MatBuf;
+
+    #[test]
+    fn test_find_child() {
+        let mut session = Session::new();
+        let ctx = Context::default();
+        let result = session.search(T, &[I, O, L], None, Mat::empty(), ctx, &Limits::default());
+        assert!(result.plan.is_ok());
+
+        let tree = session.tree.as_ref().unwrap();
+        let child = tree.children(tree.root())[3];
+        let mut matrix = MatBuf::new();
+        matrix.copy_from(tree.matrix(child));
+        let queue = tree.queue(child);
+        let mut pieces = [queue.hold().as_slice(), queue.next()].concat();
+        assert_eq!(find_child(tree, &matrix, &pieces), Some((child, 0)));
+        pieces.push(J);
+        assert_eq!(find_child(tree, &matrix, &pieces), Some((child, 1)));
+        pieces.swap(0, 1);
+        assert_eq!(find_child(tree, &matrix, &pieces), None);
+        pieces.swap(0, 1);
+        matrix.set(5, 1);
+        assert_eq!(find_child(tree, &matrix, &pieces), None);
+    }
+
+    #[test]
+    fn test_reuse() {
+        let mut session = Session::new();
+        let ctx = Context::default();
+        let limits = Limits::default();
+        session.search(T, &[I, O, L], None, Mat::empty(), ctx, &limits);
+
+        // play the first child, and reveal another piece
+        let tree = session.tree.as_ref().unwrap();
+        let child = tree.children(tree.root())[0];
+        let mut matrix = MatBuf::new();
+        matrix.copy_from(tree.matrix(child));
+        let queue = tree.queue(child);
+        let current = queue.hold().unwrap();
+        let next = [queue.next(), &[J]].concat();
+        let children = tree.children(child).len();
+        let first = tree.matrix(tree.children(child)[0]).rows().to_vec();
+
+        let result = session.search(current, &next, None, &matrix, ctx, &limits);
+        assert!(result.plan.is_ok());
+        let tree = session.tree.as_ref().unwrap();
+        assert_eq!(tree.matrix(tree.root()).rows(), matrix.rows());
+        // the children were kept, in the same order
+        assert_eq!(tree.children(tree.root()).len(), children);
+        assert_eq!(tree.matrix(tree.children(tree.root())[0]).rows(), first);
+    }
+
+    #[test]
+    fn test_explain() {
+        let mut session = Session::new();
+        assert!(session.explain().is_none());
+        let limits = Limits {
+            max_generations: Some(2),
+            ..Limits::default()
+        };
+        let plan = session
+            .search(
+                T,
+                &[I, O, L],
+                None,
+                Mat::empty(),
+                Context::default(),
+                &limits,
+            )
+            .plan
+            .unwrap();
+        assert!(!plan.inputs.is_empty());
+
+        let explanation = session.explain().unwrap();
+        assert!(!explanation.steps.is_empty());
+        assert_eq!(explanation.steps[0].matrix().rows(), Mat::empty().rows());
+        let last = explanation.steps.last().unwrap();
+        assert_eq!(explanation.breakdown.total(), last.score);
+        assert!(explanation.to_string().contains("total"));
+
+        session.reset();
+        assert!(session.explain().is_none());
+    }
+
+    #[test]
+    fn test_plan() {
+        let mut session = Session::new().with_alternatives(3);
+        let ctx = Context::default();
+        let queue = [I, O, L, S];
+        let plan = session
+            .search(T, &queue, None, Mat::empty(), ctx, &Limits::default())
+            .plan
+            .unwrap();
+
+        // every piece is placed, incl. the last one which ends up in hold
+        assert_eq!(plan.line.len(), 1 + queue.len());
+        assert_eq!(plan.line[0].hold, plan.hold);
+        assert_eq!(plan.line[0].inputs(), Some(plan.inputs.clone()));
+
+        // play the line, holding when the plan says to
+        let mut hold = None;
+        let mut pieces = [&[T][..], &queue].concat().into_iter();
+        let mut current = pieces.next();
+        for step in &plan.line {
+            if step.hold {
+                let held = hold.replace(current.unwrap());
+                current = held.or_else(|| pieces.next());
+            }
+            assert_eq!(current, Some(step.piece.piece));
+            current = pieces.next();
+            if current.is_none() {
+                current = hold.take();
+            }
+        }
+
+        assert_eq!(plan.alternatives.len(), 3);
+        let best = plan.line.last().unwrap().score;
+        let mut firsts = vec![plan.line[0].piece];
+        let mut prev = best;
+        for line in &plan.alternatives {
+            let score = line.last().unwrap().score;
+            assert!(score <= prev);
+            prev = score;
+            assert!(!firsts.contains(&line[0].piece));
+            firsts.push(line[0].piece);
+        }
+    }
+
+    #[test]
+    fn test_alternatives_transposition() {
+        // 1 ..xxxxxx..
+        // 0 ..xxxxxx..
+        let mut matrix = MatBuf::new();
+        matrix.set(0, EMPTY | 0b0011111100);
+        matrix.set(1, EMPTY | 0b0011111100);
+        let mut session = Session::new().with_alternatives(1);
+        let ctx = Context::default();
+        let plan = session
+            .search(O, &[O], None, &matrix, ctx, &Limits::default())
+            .plan
+            .unwrap();
+
+        // filling the wells in either order clears the matrix, and the second order only
+        // reaches that position through a node first reached by the first order
+        assert_eq!(plan.line.len(), 2);
+        let alternative = &plan.alternatives[0];
+        assert_ne!(alternative[0].piece, plan.line[0].piece);
+        assert_eq!(alternative[0].piece, plan.line[1].piece);
+        assert_eq!(alternative[1].piece, plan.line[0].piece);
+        assert_eq!(alternative[1].score, plan.line[1].score);
+    }
+
+    #[test]
+    fn test_outcome() {
+        let mut session = Session::new();
+        let ctx = Context::default();
+        let limits = Limits::default();
+        let outcome = session.search(T, &[I, O, L], None, Mat::empty(), ctx, &limits);
+        let plan = outcome.plan.unwrap();
+        let stats = outcome.stats;
+        assert_eq!(stats.depth, plan.line.len());
+        assert_eq!(stats.best_score, Some(plan.line.last().unwrap().score));
+        assert!(stats.nodes_expanded > 1);
+        assert!(stats.tree_size >= stats.nodes_expanded);
+        assert!(stats.memory > 0);
+
+        // fill the matrix up to the spawn position
+        let mut matrix = MatBuf::new();
+        for y in 0..21 {
+            matrix.set(y, 0b0101010101);
+        }
+        let outcome = session.search(T, &[I, O, L], None, &matrix, ctx, &limits);
+        assert_eq!(outcome.plan.err(), Some(NoMove::SpawnBlocked));
+        assert_eq!(outcome.stats.nodes_expanded, 0);
+        assert!(session.explain().is_none());
+    }
+
+    #[test]
+    fn test_opener() {
+        let shape = ["...Z......", "OOZZ..JLS.", "OOZ...JLSS", "IIII.JJLLS"];
+        let opener = Opener::new("TSD", &shape, &[]).unwrap();
+        let mut session = Session::new().with_book(Book::new(vec![opener]));
+        let ctx = Context::default();
+        let limits = Limits::default();
+
+        let mut matrix = MatBuf::new();
+        let mut queue = vec![T, I, J, L, O, S, Z];
+        let mut hold = None;
+        for _ in 0..6 {
+            let outcome = session.search(queue[0], &queue[1..], hold, &matrix, ctx, &limits);
+            assert_eq!(outcome.stats.nodes_expanded, 0);
+            let plan = outcome.plan.unwrap();
+            let current = queue.remove(0);
+            let placed = match plan.hold {
+                false => current,
+                true => hold.replace(current).unwrap_or_else(|| queue.remove(0)),
+            };
+            let piece = plan.line[0].piece;
+            assert_eq!(piece.piece, placed);
+            matrix.place(piece.cells());
+        }
+        assert_eq!(hold, Some(T));
+
+        // the opener is finished, so the search takes over
+        queue.extend([T, S, Z]);
+        let outcome = session.search(queue[0], &queue[1..], hold, &matrix, ctx, &limits);
+        assert!(outcome.stats.nodes_expanded > 0);
+    }
+}
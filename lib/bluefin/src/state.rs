@@ -1,9 +1,34 @@
-#[derive(Copy, Clone, Default, Debug, Eq, PartialEq)]
+use mino::Spin;
+
+use crate::Opponent;
+
+/// Attack sent by the combo bonus, indexed by the combo counter before the clear.
+pub const COMBO_ATTACK: [u8; 1 + MAX_COMBO as usize] = [0, 0, 1, 1, 1, 2, 2, 3, 3, 4];
+pub const MAX_COMBO: u8 = 9;
+
+/// Attack sent by line clears, indexed by number of lines cleared.
+pub const CLEAR_ATTACK: [u8; 5] = [0, 0, 1, 2, 4];
+pub const SPIN_ATTACK: [u8; 5] = [0, 2, 4, 6, 6];
+pub const MINI_SPIN_ATTACK: [u8; 5] = [0, 0, 1, 2, 4];
+pub const B2B_ATTACK: u8 = 1;
+
+/// If the stack (incl. garbage that has risen) reaches this height, then the next piece
+/// will most likely be unable to spawn.
+pub const TOP_OUT_HEIGHT: i8 = 20;
+
+#[derive(Copy, Clone, Default, Debug, Eq, PartialEq, Hash)]
 pub struct State {
-    //pub is_dead: bool,
+    pub is_dead: bool,
     pub b2b: bool,
-    //pub ren: u8,
+    pub combo: u8,
+    /// Lines of garbage queued that have not been cancelled or risen yet.
+    pub garbage: u8,
+    /// Lines of garbage that have risen into the matrix. These are not actually added to
+    /// the matrix since we don't know where their holes will be.
+    pub garbage_rows: u8,
+    pub attack: u8,
     pub b2b_clears: u8,
+    pub combo_attack: u8,
     pub single_clears: u8,
     pub double_clears: u8,
     pub triple_clears: u8,
@@ -11,47 +36,208 @@ pub struct State {
     pub spin_single_clears: u8,
     pub spin_double_clears: u8,
     pub spin_triple_clears: u8,
+    /// Height of the opponent's stack incl. garbage queued against them, which doesn't
+    /// change during the search.
+    pub opponent_height: u8,
+    /// Attack the opponent is expected to send.
+    pub threat: u8,
 }
 
 impl State {
-    pub fn new(b2b: bool) -> Self {
+    pub fn new(b2b: bool, combo: u32, garbage: u32) -> Self {
         Self {
             b2b,
+            combo: combo.min(MAX_COMBO as u32) as u8,
+            garbage: garbage.min(u8::MAX as u32) as u8,
             ..Default::default()
         }
     }
 
-    pub fn next(mut self, cleared: u8, is_spin: bool) -> Self {
+    pub fn with_opponent(mut self, opponent: Option<Opponent>) -> Self {
+        let opponent = opponent.unwrap_or_default();
+        let height = opponent.height.saturating_add(opponent.garbage);
+        self.opponent_height = height.min(u8::MAX as u32) as u8;
+        self.threat = opponent.threat.min(u8::MAX as u32) as u8;
+        self
+    }
+
+    pub fn next(mut self, cleared: u8, spin: Spin) -> Self {
         if cleared == 0 {
-            // TODO: ren
+            self.combo = 0;
+            // pessimistically assume that all queued garbage rises as soon as possible
+            self.garbage_rows = self.garbage_rows.saturating_add(self.garbage);
+            self.garbage = 0;
             return self;
         }
 
         let b2b_clear;
+        let mut attack;
 
-        if is_spin {
-            b2b_clear = true;
-            match cleared {
-                3 | 4 => self.spin_triple_clears += 1,
-                2 => self.spin_double_clears += 1,
-                _ => self.spin_single_clears += 1,
+        match spin {
+            Spin::Full => {
+                b2b_clear = true;
+                attack = SPIN_ATTACK[cleared.min(4) as usize];
+                let clears = match cleared {
+                    3 | 4 => &mut self.spin_triple_clears,
+                    2 => &mut self.spin_double_clears,
+                    _ => &mut self.spin_single_clears,
+                };
+                *clears = clears.saturating_add(1);
             }
-        } else {
-            b2b_clear = cleared >= 4;
-            match cleared {
-                4 => self.quad_clears += 1,
-                3 => self.triple_clears += 1,
-                2 => self.double_clears += 1,
-                _ => self.single_clears += 1,
+            Spin::Mini | Spin::None => {
+                // minis are counted like regular clears, but keep back-to-back
+                b2b_clear = spin == Spin::Mini || cleared >= 4;
+                attack = match spin {
+                    Spin::Mini => MINI_SPIN_ATTACK[cleared.min(4) as usize],
+                    _ => CLEAR_ATTACK[cleared.min(4) as usize],
+                };
+                let clears = match cleared {
+                    4 => &mut self.quad_clears,
+                    3 => &mut self.triple_clears,
+                    2 => &mut self.double_clears,
+                    _ => &mut self.single_clears,
+                };
+                *clears = clears.saturating_add(1);
             }
         }
 
-        // TODO: ren
+        let combo_attack = COMBO_ATTACK[self.combo as usize];
+        attack += combo_attack;
+        self.combo_attack = self.combo_attack.saturating_add(combo_attack);
+        self.combo = (self.combo + 1).min(MAX_COMBO);
 
         if b2b_clear && self.b2b {
-            self.b2b_clears += 1;
+            attack += B2B_ATTACK;
+            self.b2b_clears = self.b2b_clears.saturating_add(1);
         }
         self.b2b = b2b_clear;
+
+        let cancel = attack.min(self.garbage);
+        self.garbage -= cancel;
+        self.attack = self.attack.saturating_add(attack - cancel);
         self
     }
+
+    /// Mark the state as dead if the stack is too high, given the height of the matrix
+    /// after placing a piece.
+    pub fn check_top_out(mut self, height: i8) -> Self {
+        if height as i32 + self.garbage_rows as i32 >= TOP_OUT_HEIGHT as i32 {
+            self.is_dead = true;
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_combo() {
+        let st = State::new(false, 0, 0);
+        let st = st.next(1, Spin::None);
+        assert_eq!((st.combo, st.combo_attack), (1, 0));
+        let st = st.next(1, Spin::None).next(2, Spin::None);
+        assert_eq!((st.combo, st.combo_attack), (3, 1));
+        let st = st.next(0, Spin::None);
+        assert_eq!((st.combo, st.combo_attack), (0, 1));
+    }
+
+    #[test]
+    fn test_combo_initial() {
+        let st = State::new(false, 5, 0).next(1, Spin::None);
+        assert_eq!((st.combo, st.combo_attack), (6, 2));
+        let st = State::new(false, 100, 0).next(1, Spin::None);
+        assert_eq!((st.combo, st.combo_attack), (MAX_COMBO, 4));
+    }
+
+    #[test]
+    fn test_b2b() {
+        let st = State::new(true, 0, 0).next(4, Spin::None);
+        assert_eq!(st.b2b_clears, 1);
+        assert!(st.b2b);
+        let st = State::new(false, 0, 0).next(4, Spin::None);
+        assert_eq!(st.b2b_clears, 0);
+        assert!(st.b2b);
+        let st = st.next(2, Spin::Full).next(1, Spin::None);
+        assert_eq!(st.b2b_clears, 1);
+        assert!(!st.b2b);
+    }
+
+    #[test]
+    fn test_mini() {
+        let st = State::new(true, 0, 0).next(2, Spin::Mini);
+        assert_eq!((st.attack, st.double_clears, st.b2b_clears), (2, 1, 1));
+        assert!(st.b2b);
+        let st = st.next(1, Spin::Mini);
+        assert_eq!(st.attack, 2 + B2B_ATTACK);
+    }
+
+    // the tables are copied from botris, which is an optional dependency
+    #[cfg(feature = "botris")]
+    #[test]
+    fn test_botris_tables() {
+        use botris::score;
+        let widen = |table: &[u8]| table.iter().map(|&x| x as u32).collect::<Vec<_>>();
+        assert_eq!(widen(&COMBO_ATTACK), score::COMBO);
+        assert_eq!(MAX_COMBO as usize, score::MAX_COMBO);
+        let clear = [0, score::SINGLE, score::DOUBLE, score::TRIPLE, score::QUAD];
+        assert_eq!(widen(&CLEAR_ATTACK), clear);
+        let spin = [
+            0,
+            score::SPIN_SINGLE,
+            score::SPIN_DOUBLE,
+            score::SPIN_TRIPLE,
+            score::SPIN_TRIPLE,
+        ];
+        assert_eq!(widen(&SPIN_ATTACK), spin);
+        let mini = [
+            0,
+            score::MINI_SPIN_SINGLE,
+            score::MINI_SPIN_DOUBLE,
+            score::TRIPLE,
+            score::QUAD,
+        ];
+        assert_eq!(widen(&MINI_SPIN_ATTACK), mini);
+        assert_eq!(B2B_ATTACK as u32, score::B2B);
+    }
+
+    #[test]
+    fn test_garbage_cancel() {
+        let st = State::new(false, 0, 5).next(4, Spin::None);
+        assert_eq!((st.garbage, st.attack), (1, 0));
+        let st = st.next(3, Spin::Full);
+        assert_eq!((st.garbage, st.attack), (0, 6));
+        assert_eq!(st.garbage_rows, 0);
+    }
+
+    #[test]
+    fn test_garbage_rise() {
+        let st = State::new(false, 0, 3).next(0, Spin::None);
+        assert_eq!((st.garbage, st.garbage_rows), (0, 3));
+        assert!(!st.check_top_out(16).is_dead);
+        assert!(st.check_top_out(17).is_dead);
+        let st = st.next(2, Spin::None);
+        assert_eq!((st.garbage, st.garbage_rows, st.attack), (0, 3, 1));
+    }
+
+    #[test]
+    fn test_garbage_rise_large() {
+        let st = State::new(false, 0, 200).next(0, Spin::None);
+        assert_eq!(st.garbage_rows, 200);
+        assert!(st.check_top_out(0).is_dead);
+        assert!(st.check_top_out(i8::MAX).is_dead);
+    }
+
+    #[test]
+    fn test_counters_saturate() {
+        let mut st = State::new(true, 0, 0);
+        for _ in 0..300 {
+            st = st.next(2, Spin::Full);
+        }
+        assert_eq!(st.spin_double_clears, u8::MAX);
+        assert_eq!(st.b2b_clears, u8::MAX);
+        assert_eq!(st.combo_attack, u8::MAX);
+        assert_eq!(st.attack, u8::MAX);
+    }
 }
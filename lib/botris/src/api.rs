@@ -106,10 +106,10 @@ pub struct RoomData {
     // private: boolean;
     pub ft: u32,
     pub pps: f32,
-    // initialMessiness: number;
-    // finalMessiness: number;
-    // startMargin: number;
-    // endMargin: number;
+    pub initial_messiness: f32,
+    pub final_messiness: f32,
+    pub start_margin: f32,
+    pub end_margin: f32,
     pub max_players: u32,
     pub game_ongoing: bool,
     // roundOngoing: boolean;
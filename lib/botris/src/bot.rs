@@ -0,0 +1,34 @@
+//! Interface for bots, so that different engines can be plugged into the same runners.
+
+use crate::game::{Command, GameState};
+
+/// A bot plays a game by producing a list of commands whenever a move is requested. The
+/// other methods are optional hooks that notify the bot about events in the game.
+pub trait Bot {
+    /// Compute the next move for `game_state`. The returned commands will be followed by
+    /// a hard drop. Returning `None` means the bot has given up.
+    fn request_move(&mut self, game_state: &GameState) -> Option<Vec<Command>>;
+
+    /// Called before the start of each round.
+    fn new_round(&mut self) {}
+
+    /// Called when `lines` lines of garbage were added to the bot's garbage queue.
+    fn garbage_received(&mut self, lines: u32, game_state: &GameState) {
+        let _ = (lines, game_state);
+    }
+
+    /// Called when the game state of an opponent changes. `opponent` identifies the
+    /// opponent, e.g. by its session id.
+    fn opponent_update(&mut self, opponent: &str, game_state: &GameState) {
+        let _ = (opponent, game_state);
+    }
+}
+
+impl<F> Bot for F
+where
+    F: FnMut(&GameState) -> Option<Vec<Command>>,
+{
+    fn request_move(&mut self, game_state: &GameState) -> Option<Vec<Command>> {
+        self(game_state)
+    }
+}
@@ -0,0 +1,363 @@
+//! Conversions between Botris game types and [`mino`] types.
+//!
+//! Both use the same coordinate system: `x` increases to the right, `y` increases
+//! upwards, and a piece's position is the top-left corner of its bounding box in the
+//! north orientation. This means positions can be converted without any adjustment.
+
+use mino::matrix::{Mat, MatBuf};
+use mino::standard_rules;
+use mino::{Input, Rot};
+
+use crate::game::{Board, Command, NonEmptyBlock, Piece, PieceData, Rotation, Spin, SpinRules};
+
+impl From<&Board> for MatBuf {
+    fn from(board: &Board) -> Self {
+        let mut matrix = MatBuf::new();
+        for (y, row) in board.rows().iter().enumerate() {
+            let bits = row
+                .iter()
+                .enumerate()
+                .filter(|(_, cell)| cell.is_some())
+                .fold(0u16, |bits, (x, _)| bits | (1 << x));
+            matrix.set(y as i8, bits);
+        }
+        matrix
+    }
+}
+
+/// Since [`Mat`] does not track what piece each cell came from, all occupied cells are
+/// converted to garbage blocks.
+impl From<&Mat> for Board {
+    fn from(matrix: &Mat) -> Self {
+        let mut board = Board::new();
+        for y in 0..matrix.len() {
+            let row = matrix.get(y);
+            for x in 0..matrix.cols() {
+                if row & (1 << x) != 0 {
+                    board[(x, y)] = Some(NonEmptyBlock::G);
+                }
+            }
+        }
+        board
+    }
+}
+
+impl From<Piece> for standard_rules::Piece {
+    fn from(pc: Piece) -> Self {
+        match pc {
+            Piece::I => standard_rules::I,
+            Piece::J => standard_rules::J,
+            Piece::L => standard_rules::L,
+            Piece::O => standard_rules::O,
+            Piece::S => standard_rules::S,
+            Piece::T => standard_rules::T,
+            Piece::Z => standard_rules::Z,
+        }
+    }
+}
+
+impl From<standard_rules::Piece> for Piece {
+    fn from(pc: standard_rules::Piece) -> Self {
+        match pc {
+            standard_rules::I => Piece::I,
+            standard_rules::J => Piece::J,
+            standard_rules::L => Piece::L,
+            standard_rules::O => Piece::O,
+            standard_rules::S => Piece::S,
+            standard_rules::T => Piece::T,
+            standard_rules::Z => Piece::Z,
+        }
+    }
+}
+
+impl From<Rotation> for Rot {
+    fn from(r: Rotation) -> Self {
+        u8::from(r).into()
+    }
+}
+
+impl From<Rot> for Rotation {
+    fn from(r: Rot) -> Self {
+        u8::from(r).into()
+    }
+}
+
+impl From<PieceData> for standard_rules::FallingPiece {
+    fn from(pd: PieceData) -> Self {
+        Self::new(pd.piece.into(), (pd.x, pd.y, pd.rotation.into()))
+    }
+}
+
+impl From<standard_rules::FallingPiece> for PieceData {
+    fn from(fp: standard_rules::FallingPiece) -> Self {
+        Self {
+            piece: fp.piece.into(),
+            rotation: fp.pos.r.into(),
+            x: fp.pos.x,
+            y: fp.pos.y,
+        }
+    }
+}
+
+impl From<Spin> for mino::Spin {
+    fn from(spin: Spin) -> Self {
+        match spin {
+            Spin::None => Self::None,
+            Spin::Mini => Self::Mini,
+            Spin::Full => Self::Full,
+        }
+    }
+}
+
+impl From<SpinRules> for mino::SpinRules {
+    fn from(rules: SpinRules) -> Self {
+        match rules {
+            SpinRules::AllSpin => Self::AllSpin,
+            SpinRules::TSpin => Self::TSpin,
+            SpinRules::AllMini => Self::AllMini,
+        }
+    }
+}
+
+/// Botris has no 180 degree rotations, so [`Input::Half`] is returned as the error.
+impl TryFrom<Input> for Command {
+    type Error = Input;
+
+    fn try_from(inp: Input) -> Result<Self, Input> {
+        match inp {
+            Input::Left => Ok(Command::MoveLeft),
+            Input::Right => Ok(Command::MoveRight),
+            Input::Cw => Ok(Command::RotateCw),
+            Input::Ccw => Ok(Command::RotateCcw),
+            Input::SonicDrop => Ok(Command::SonicDrop),
+            Input::Half => Err(inp),
+        }
+    }
+}
+
+/// Convert an input sequence (as returned by [`mino::reach`]) into a list of commands,
+/// preceded by [`Command::Hold`] if `hold` is true. Returns `None` if any of the inputs
+/// has no equivalent command, which can't happen with [`standard_rules`] pieces.
+pub fn commands(hold: bool, inputs: &[Input]) -> Option<Vec<Command>> {
+    let mut cmds = Vec::with_capacity(inputs.len() + 1);
+    if hold {
+        cmds.push(Command::Hold);
+    }
+    for &inp in inputs {
+        cmds.push(Command::try_from(inp).ok()?);
+    }
+    Some(cmds)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::ALL_PIECES;
+    use mino::standard_rules::FallingPiece;
+    use mino::{Dir, Turn};
+
+    static ALL_ROTATIONS: [Rotation; 4] = [
+        Rotation::North,
+        Rotation::East,
+        Rotation::South,
+        Rotation::West,
+    ];
+
+    fn sorted_coords(coords: impl Iterator<Item = (i8, i8)>) -> Vec<(i8, i8)> {
+        let mut coords = coords.collect::<Vec<_>>();
+        coords.sort();
+        coords
+    }
+
+    fn sample_board() -> Board {
+        // 3 ...x......
+        // 2 xx.xx....x
+        // 1 xxxxx.xxxx
+        // 0 xxxx.xxxxx
+        //   0123456789
+        let mut board = Board::new();
+        for (y, row) in ["xxxx.xxxxx", "xxxxx.xxxx", "xx.xx....x", "...x......"]
+            .iter()
+            .enumerate()
+        {
+            for (x, c) in row.chars().enumerate() {
+                if c == 'x' {
+                    board[(x as i8, y as i8)] = Some(NonEmptyBlock::G);
+                }
+            }
+        }
+        board
+    }
+
+    #[test]
+    fn test_piece_round_trip() {
+        for pc in ALL_PIECES {
+            let mino_pc = standard_rules::Piece::from(pc);
+            assert_eq!(pc.name(), mino_pc.name());
+            assert_eq!(Piece::from(mino_pc), pc);
+        }
+    }
+
+    #[test]
+    fn test_board_round_trip() {
+        let board = sample_board();
+        let matrix = MatBuf::from(&board);
+        assert_eq!(matrix.len(), board.len());
+        for y in -1..6 {
+            for x in -1..11 {
+                let occ = !(0..10).contains(&x) || matrix.get(y) & (1 << x) != 0;
+                assert_eq!(occ, board[(x, y)].is_some(), "({x}, {y})");
+            }
+        }
+        assert_eq!(Board::from(&*matrix), board);
+    }
+
+    #[test]
+    fn test_piece_data_cells() {
+        for pc in ALL_PIECES {
+            for r in ALL_ROTATIONS {
+                for (x, y) in [(3, 20), (0, 5), (-1, 2), (7, 10)] {
+                    let pd = PieceData {
+                        piece: pc,
+                        rotation: r,
+                        x,
+                        y,
+                    };
+                    let fp = FallingPiece::from(pd);
+                    assert_eq!(PieceData::from(fp), pd);
+                    assert_eq!(
+                        sorted_coords(pd.coords()),
+                        sorted_coords(fp.cells().coords()),
+                        "{pd:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_spawn() {
+        for pc in ALL_PIECES {
+            let fp = FallingPiece::spawn(pc.into());
+            assert_eq!(PieceData::from(fp), PieceData::spawn(pc));
+        }
+    }
+
+    // apply the same inputs to both representations and check that they agree after
+    // every step, incl. wall kicks
+    fn assert_same_inputs(board: &Board, pc: Piece, inputs: &[Input]) {
+        let matrix = MatBuf::from(board);
+        let mut pd = PieceData::spawn(pc);
+        let mut fp = FallingPiece::spawn(pc.into());
+        for &inp in inputs {
+            let ok = match inp {
+                Input::Left => fp.try_shift(&matrix, Dir::Left).is_some(),
+                Input::Right => fp.try_shift(&matrix, Dir::Right).is_some(),
+                Input::Cw => fp.try_rotate(&matrix, Turn::Cw).is_some(),
+                Input::Ccw => fp.try_rotate(&matrix, Turn::Ccw).is_some(),
+                Input::SonicDrop => fp.sonic_drop(&matrix).0 != 0,
+                Input::Half => unreachable!(),
+            };
+            let ok2 = match Command::try_from(inp).unwrap() {
+                Command::MoveLeft => pd.try_offset((-1, 0), board),
+                Command::MoveRight => pd.try_offset((1, 0), board),
+                Command::RotateCw => pd.try_rotate_cw(board),
+                Command::RotateCcw => pd.try_rotate_ccw(board),
+                Command::SonicDrop => pd.sonic_drop(board) != 0,
+                cmd => panic!("unexpected command {cmd:?}"),
+            };
+            assert_eq!(ok, ok2, "{pc:?} {inputs:?}");
+            assert_eq!(PieceData::from(fp), pd, "{pc:?} {inputs:?}");
+        }
+    }
+
+    #[test]
+    fn test_inputs_agree() {
+        use Input::*;
+        let board = sample_board();
+        let sequences: &[&[Input]] = &[
+            &[Left, Left, Left, Left, SonicDrop],
+            &[Cw, SonicDrop, Cw, Cw],
+            &[Ccw, SonicDrop, Ccw, Right, Ccw],
+            &[Right, Right, SonicDrop, Cw, Cw, Left, Ccw],
+            &[Cw, Right, Right, Right, Right, SonicDrop, Ccw, Ccw],
+            &[SonicDrop, Cw, Cw, Cw, Cw, Left, Ccw, Ccw],
+        ];
+        for pc in ALL_PIECES {
+            for inputs in sequences {
+                assert_same_inputs(&board, pc, inputs);
+                assert_same_inputs(&Board::new(), pc, inputs);
+            }
+        }
+    }
+
+    #[test]
+    fn test_spin_rules_agree() {
+        use mino::spin::Kick;
+        // a T pointing up in the bottom left corner has 3 corners filled, but only one of
+        // them in front
+        let mut mini = MatBuf::new();
+        mini.set(1, 0b1);
+        let mut spins = Vec::new();
+        for board in [sample_board(), Board::from(&*mini)] {
+            let matrix = MatBuf::from(&board);
+            for pl in mino::places(&matrix, standard_rules::Piece::T) {
+                let fp = pl.falling_piece;
+                let pd = PieceData::from(fp);
+                for turn in [Turn::Cw, Turn::Ccw] {
+                    let r0 = Rotation::from(fp.pos.r + turn.inverse());
+                    for index in 0..5 {
+                        let kick = Kick { turn, index };
+                        let rules = SpinRules::TSpin;
+                        let spin = board.check_spin(pd, rules, Some((r0, index))).into();
+                        assert_eq!(
+                            spin,
+                            mino::SpinRules::from(rules).spin(&matrix, fp, Some(kick)),
+                            "{fp:?} {kick:?}"
+                        );
+                        spins.push(spin);
+                    }
+                }
+            }
+        }
+        assert!(spins.contains(&mino::Spin::Mini));
+        assert!(spins.contains(&mino::Spin::Full));
+    }
+
+    #[test]
+    fn test_reach_commands() {
+        let board = sample_board();
+        let matrix = MatBuf::from(&board);
+        for pc in ALL_PIECES {
+            for pl in mino::places(&matrix, pc.into()) {
+                let inputs = mino::reach(&matrix, pl.falling_piece).unwrap();
+                let mut game = crate::game::Game::new_seeded(0);
+                game.state.board = board.clone();
+                game.state.current = PieceData::spawn(pc);
+                for cmd in commands(false, &inputs).unwrap() {
+                    assert!(game.perform_command(cmd), "{pc:?} {inputs:?}");
+                }
+                game.perform_command(Command::SonicDrop);
+                // `reach` only guarantees the same cells, e.g. I piece east and west
+                // orientations can be equivalent
+                let target = PieceData::from(pl.falling_piece);
+                assert_eq!(
+                    sorted_coords(game.current.coords()),
+                    sorted_coords(target.coords()),
+                    "{inputs:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_commands_hold() {
+        use Input::*;
+        assert_eq!(
+            commands(true, &[Left, Cw]).unwrap(),
+            [Command::Hold, Command::MoveLeft, Command::RotateCw]
+        );
+        assert_eq!(commands(false, &[SonicDrop]).unwrap(), [Command::SonicDrop]);
+        assert_eq!(commands(false, &[Cw, Half]), None);
+    }
+}
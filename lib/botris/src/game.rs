@@ -36,6 +36,53 @@ impl Board {
             .all(|&ofs| self.check_collision(piece_data.offset(ofs)))
     }
 
+    /// Returns the spin of placing `piece_data` according to `rules`. `last_kick` is the
+    /// rotation that the last rotation started from and the index of the wall kick it
+    /// used, or `None` if the last move was not a rotation.
+    pub fn check_spin(
+        &self,
+        piece_data: PieceData,
+        rules: SpinRules,
+        last_kick: Option<(Rotation, usize)>,
+    ) -> Spin {
+        let immobile = || self.check_immobile(piece_data);
+        match rules {
+            SpinRules::AllSpin if immobile() => Spin::Full,
+            SpinRules::AllSpin => Spin::None,
+            _ if piece_data.piece == Piece::T => self.check_t_spin(piece_data, last_kick),
+            SpinRules::AllMini if immobile() => Spin::Mini,
+            _ => Spin::None,
+        }
+    }
+
+    // 3-corner rule for the T piece
+    fn check_t_spin(&self, piece_data: PieceData, last_kick: Option<(Rotation, usize)>) -> Spin {
+        let Some((r0, kick)) = last_kick else {
+            return Spin::None;
+        };
+        // using the last wall kick tested makes a mini count as a full spin
+        let last = kick + 1 == piece_data.piece.wall_kicks(r0, piece_data.rotation).count();
+        let (cx, cy) = (piece_data.x + 1, piece_data.y - 1);
+        // direction the T points in
+        let (dx, dy) = match piece_data.rotation {
+            Rotation::North => (0, 1),
+            Rotation::East => (1, 0),
+            Rotation::South => (0, -1),
+            Rotation::West => (-1, 0),
+        };
+        let front = [(cx + dx - dy, cy + dy - dx), (cx + dx + dy, cy + dy + dx)];
+        let back = [(cx - dx - dy, cy - dy - dx), (cx - dx + dy, cy - dy + dx)];
+        let front = front.iter().filter(|&&xy| self[xy].is_some()).count();
+        let back = back.iter().filter(|&&xy| self[xy].is_some()).count();
+        if front + back < 3 {
+            Spin::None
+        } else if front == 2 || last {
+            Spin::Full
+        } else {
+            Spin::Mini
+        }
+    }
+
     pub fn place_piece(&mut self, piece_data: PieceData) {
         let block = Some(piece_data.piece.into());
         piece_data.coords().for_each(|xy| self[xy] = block);
@@ -58,6 +105,19 @@ impl Board {
         rows.truncate(y_dst);
         cleared
     }
+
+    /// Insert a garbage row at the bottom of the board, with an empty cell in column
+    /// `hole`. Every existing row is moved up by one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `hole` is not a column of the board.
+    pub fn insert_garbage(&mut self, hole: i8) {
+        assert!((0..10).contains(&hole), "garbage hole out of range: {hole}");
+        let mut row = [Some(NonEmptyBlock::G); 10];
+        row[hole as usize] = None;
+        self.0.insert(0, row);
+    }
 }
 
 impl std::ops::Index<(i8, i8)> for Board {
@@ -146,23 +206,25 @@ impl PieceData {
     }
 
     pub fn try_rotate_cw(&mut self, board: &Board) -> bool {
-        self.try_rotate(self.rotation.cw(), board)
+        self.try_rotate(self.rotation.cw(), board).is_some()
     }
 
     pub fn try_rotate_ccw(&mut self, board: &Board) -> bool {
-        self.try_rotate(self.rotation.ccw(), board)
+        self.try_rotate(self.rotation.ccw(), board).is_some()
     }
 
-    fn try_rotate(&mut self, new_r: Rotation, board: &Board) -> bool {
+    /// Try to rotate to `new_r`, applying wall kicks. Returns the index of the wall kick
+    /// that was used, or `None` if the piece could not be rotated.
+    pub fn try_rotate(&mut self, new_r: Rotation, board: &Board) -> Option<usize> {
         let old_r = self.rotation;
-        for ofs in self.piece.wall_kicks(old_r, new_r) {
+        for (i, ofs) in self.piece.wall_kicks(old_r, new_r).enumerate() {
             let kicked = self.rotate(new_r).offset(ofs);
             if !board.check_collision(kicked) {
                 *self = kicked;
-                return true;
+                return Some(i);
             }
         }
-        false
+        None
     }
 
     pub fn sonic_drop(&mut self, board: &Board) -> i8 {
@@ -359,6 +421,39 @@ impl std::fmt::Display for Rotation {
     }
 }
 
+/// Kind of spin of a placement.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Spin {
+    #[default]
+    None,
+    Mini,
+    Full,
+}
+
+impl Spin {
+    /// Returns `true` for both full and mini spins.
+    pub fn is_spin(self) -> bool {
+        self != Spin::None
+    }
+}
+
+/// Set of rules deciding which placements are spins.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Hash)]
+pub enum SpinRules {
+    /// Any piece that is immobile is a full spin.
+    #[default]
+    AllSpin,
+    /// Only T pieces can spin, following the 3-corner rule: the last move must be a
+    /// rotation, and 3 of the 4 corners around the center of the T must be filled. It is
+    /// a mini unless both corners on the pointing side are filled, or the rotation used
+    /// the last wall kick.
+    TSpin,
+    /// T pieces follow [`SpinRules::TSpin`], and any other piece that is immobile is a
+    /// mini spin.
+    AllMini,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GameState {
@@ -381,7 +476,63 @@ pub struct GarbageLine {
     pub delay: u32,
 }
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+/// Settings controlling how garbage is queued and inserted into the board.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GarbageRules {
+    /// Number of pieces that must be placed before a queued line may be tanked.
+    pub delay: u32,
+    /// Chance that the hole moves to a different column between two garbage lines, at
+    /// the start of the round.
+    pub initial_messiness: f32,
+    /// Chance that the hole moves, after `end_margin` has elapsed.
+    pub final_messiness: f32,
+    /// Time (in seconds) at which messiness starts increasing from its initial value.
+    pub start_margin: f32,
+    /// Time (in seconds) at which messiness reaches its final value.
+    pub end_margin: f32,
+}
+
+impl GarbageRules {
+    /// Compute the messiness at `elapsed` seconds into the round, interpolating linearly
+    /// between the initial and final messiness.
+    pub fn messiness(&self, elapsed: f32) -> f32 {
+        let t = if self.end_margin > self.start_margin {
+            (elapsed - self.start_margin) / (self.end_margin - self.start_margin)
+        } else if elapsed >= self.end_margin {
+            1.0
+        } else {
+            0.0
+        };
+        let t = t.clamp(0.0, 1.0);
+        self.initial_messiness + (self.final_messiness - self.initial_messiness) * t
+    }
+}
+
+impl Default for GarbageRules {
+    fn default() -> Self {
+        Self {
+            delay: 1,
+            initial_messiness: 0.0,
+            final_messiness: 0.0,
+            start_margin: 0.0,
+            end_margin: 0.0,
+        }
+    }
+}
+
+impl From<&crate::api::RoomData> for GarbageRules {
+    fn from(room_data: &crate::api::RoomData) -> Self {
+        Self {
+            initial_messiness: room_data.initial_messiness,
+            final_messiness: room_data.final_messiness,
+            start_margin: room_data.start_margin,
+            end_margin: room_data.end_margin,
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 #[repr(u8)]
 pub enum Command {
@@ -397,11 +548,47 @@ pub enum Command {
     HardDrop,
 }
 
+/// Summary of the result of a single move, i.e. the events that happened when the piece
+/// was hard dropped.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MoveOutcome {
+    /// Piece that was placed.
+    pub piece: Piece,
+    /// Number of lines cleared.
+    pub cleared: i8,
+    /// Kind of spin that cleared the lines, according to the spin rules of the game.
+    pub spin: Spin,
+    /// The clear was back-to-back with a previous "difficult" clear.
+    pub b2b: bool,
+    /// Combo counter after the move.
+    pub combo: u32,
+    /// Raw attack generated by the move, before cancelling.
+    pub score: u32,
+    /// Amount of attack used to cancel queued garbage.
+    pub cancelled: u32,
+    /// Amount of attack left over to be sent to an opponent.
+    pub sent: u32,
+    /// Number of garbage lines that were inserted into the board.
+    pub tanked: u32,
+    /// The player topped out as a result of the move.
+    pub dead: bool,
+    /// Commands that could not be performed.
+    pub blocked: Vec<Command>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Game {
     pub state: GameState,
     rng: SmallRng,
     bag: Vec<Piece>,
+    garbage_rules: GarbageRules,
+    garbage_rng: SmallRng,
+    garbage_hole: i8,
+    messiness: f32,
+    spin_rules: SpinRules,
+    // rotation started from and wall kick used by the last move, if it was a rotation
+    last_kick: Option<(Rotation, usize)>,
 }
 
 impl std::ops::Deref for Game {
@@ -417,10 +604,15 @@ impl Game {
     }
 
     pub fn new_seeded(s: u64) -> Self {
-        Self::with_rng(SmallRng::seed_from_u64(s))
+        Self::with_rng(
+            SmallRng::seed_from_u64(s),
+            SmallRng::seed_from_u64(s ^ GARBAGE_SEED_SALT),
+        )
     }
 
-    fn with_rng(rng: SmallRng) -> Self {
+    fn with_rng(rng: SmallRng, mut garbage_rng: SmallRng) -> Self {
+        let garbage_rules = GarbageRules::default();
+        let garbage_hole = garbage_rng.gen_range(0..10);
         let mut this = Game {
             state: GameState {
                 board: Board::new(),
@@ -437,25 +629,73 @@ impl Game {
             },
             rng,
             bag: Vec::with_capacity(7),
+            garbage_rules,
+            garbage_rng,
+            garbage_hole,
+            messiness: garbage_rules.messiness(0.0),
+            spin_rules: SpinRules::default(),
+            last_kick: None,
         };
 
         this.spawn_piece();
         this
     }
 
-    // TODO: return list of attacks
-    pub fn perform_commands(&mut self, cmds: &[Command]) {
+    /// Replace the rules used for queueing and tanking garbage.
+    pub fn with_garbage_rules(mut self, rules: GarbageRules) -> Self {
+        self.garbage_rules = rules;
+        self.messiness = rules.messiness(0.0);
+        self
+    }
+
+    pub fn garbage_rules(&self) -> &GarbageRules {
+        &self.garbage_rules
+    }
+
+    /// Replace the rules deciding which placements are spins.
+    pub fn with_spin_rules(mut self, rules: SpinRules) -> Self {
+        self.spin_rules = rules;
+        self
+    }
+
+    pub fn spin_rules(&self) -> SpinRules {
+        self.spin_rules
+    }
+
+    /// Update the garbage messiness according to the time (in seconds) elapsed since the
+    /// start of the round.
+    pub fn set_elapsed(&mut self, elapsed: f32) {
+        self.messiness = self.garbage_rules.messiness(elapsed);
+    }
+
+    /// Receive `lines` lines of attack. They are added to the end of `garbage_queued`, and
+    /// will be tanked once their delay runs out, unless they are cancelled first.
+    pub fn queue_garbage(&mut self, lines: u32) {
+        let delay = self.garbage_rules.delay;
+        let lines = (0..lines).map(|_| GarbageLine { delay });
+        self.state.garbage_queued.extend(lines);
+    }
+
+    /// Perform the list of commands and then hard drop the piece. Returns a summary of
+    /// what happened as a result of the move.
+    pub fn perform_commands(&mut self, cmds: &[Command]) -> MoveOutcome {
+        let mut blocked = Vec::new();
         for &cmd in cmds.iter() {
             if !self.perform_command(cmd) {
                 warn!("command blocked: {cmd:?} ({:?})", self.current);
+                blocked.push(cmd);
             }
         }
 
-        self.perform_command(Command::HardDrop);
+        MoveOutcome {
+            blocked,
+            ..self.hard_drop()
+        }
     }
 
     pub fn perform_command(&mut self, cmd: Command) -> bool {
-        match cmd {
+        let before = self.state.current;
+        let done = match cmd {
             Command::MoveLeft => self.state.current.try_offset((-1, 0), &self.state.board),
             Command::MoveRight => self.state.current.try_offset((1, 0), &self.state.board),
             Command::SonicLeft => {
@@ -467,8 +707,8 @@ impl Game {
                 true
             }
             Command::Drop => self.state.current.try_offset((-1, 0), &self.state.board),
-            Command::RotateCw => self.state.current.try_rotate_cw(&self.state.board),
-            Command::RotateCcw => self.state.current.try_rotate_ccw(&self.state.board),
+            Command::RotateCw => self.rotate(self.state.current.rotation.cw()),
+            Command::RotateCcw => self.rotate(self.state.current.rotation.ccw()),
             Command::SonicDrop => self.state.current.sonic_drop(&self.state.board) != 0,
 
             Command::Hold => {
@@ -487,38 +727,73 @@ impl Game {
             }
 
             Command::HardDrop => {
-                self.state.current.sonic_drop(&self.state.board);
-                let immobile = self.state.board.check_immobile(self.state.current);
-                debug!(piece = ?self.current, "lock in");
-                self.state.board.place_piece(self.current);
-                let cleared = self.state.board.clear_lines();
-
-                let score = calculate_score(
-                    cleared,
-                    immobile,
-                    &mut self.state.b2b,
-                    &mut self.state.combo,
-                );
-
-                /* TODO: apply multiplier to score */
-
-                debug!(
-                    cleared,
-                    immobile,
-                    score,
-                    b2b = self.b2b,
-                    combo = self.combo,
-                    "hard drop"
-                );
-
-                self.state.score += score;
-                self.state.pieces_placed += 1;
-
-                let (_cancel, _attack) = self.cancel_garbage(score);
-                self.tank_garbage();
-                self.spawn_piece();
+                self.hard_drop();
                 true
             }
+        };
+        let shifted = !matches!(cmd, Command::RotateCw | Command::RotateCcw);
+        if shifted && self.state.current != before {
+            self.last_kick = None;
+        }
+        done
+    }
+
+    fn rotate(&mut self, r: Rotation) -> bool {
+        let r0 = self.state.current.rotation;
+        let kick = self.state.current.try_rotate(r, &self.state.board);
+        if let Some(kick) = kick {
+            self.last_kick = Some((r0, kick));
+        }
+        kick.is_some()
+    }
+
+    fn hard_drop(&mut self) -> MoveOutcome {
+        let piece = self.state.current.piece;
+        if self.state.current.sonic_drop(&self.state.board) != 0 {
+            self.last_kick = None;
+        }
+        let spin = self
+            .state
+            .board
+            .check_spin(self.state.current, self.spin_rules, self.last_kick);
+        debug!(piece = ?self.current, "lock in");
+        self.state.board.place_piece(self.current);
+        let cleared = self.state.board.clear_lines();
+
+        let was_b2b = self.state.b2b;
+        let score = calculate_score(cleared, spin, &mut self.state.b2b, &mut self.state.combo);
+
+        /* TODO: apply multiplier to score */
+
+        debug!(
+            cleared,
+            ?spin,
+            score,
+            b2b = self.b2b,
+            combo = self.combo,
+            "hard drop"
+        );
+
+        self.state.score += score;
+        self.state.pieces_placed += 1;
+
+        let (cancelled, sent) = self.cancel_garbage(score);
+        let tanked = if cleared == 0 { self.tank_garbage() } else { 0 };
+        self.tick_garbage();
+        self.spawn_piece();
+
+        MoveOutcome {
+            piece,
+            cleared,
+            spin: if cleared > 0 { spin } else { Spin::None },
+            b2b: cleared > 0 && was_b2b && self.state.b2b,
+            combo: self.state.combo,
+            score,
+            cancelled,
+            sent,
+            tanked,
+            dead: self.state.dead,
+            blocked: Vec::new(),
         }
     }
 
@@ -536,6 +811,7 @@ impl Game {
         self.state.current = PieceData::spawn(piece);
         self.state.dead = self.state.board.check_collision(self.state.current);
         self.state.can_hold = true;
+        self.last_kick = None;
     }
 
     fn cancel_garbage(&mut self, attack: u32) -> (u32, u32) {
@@ -544,11 +820,39 @@ impl Game {
         (cancel as u32, attack - cancel as u32)
     }
 
-    fn tank_garbage(&mut self) {
-        // ...
+    fn tank_garbage(&mut self) -> u32 {
+        let ready = self
+            .state
+            .garbage_queued
+            .iter()
+            .take_while(|line| line.delay == 0)
+            .count();
+        if ready == 0 {
+            return 0;
+        }
+
+        for _ in 0..ready {
+            if self.garbage_rng.gen::<f32>() < self.messiness {
+                let shift = self.garbage_rng.gen_range(1..10);
+                self.garbage_hole = (self.garbage_hole + shift) % 10;
+            }
+            self.state.board.insert_garbage(self.garbage_hole);
+        }
+
+        debug!(lines = ready, hole = self.garbage_hole, "tank garbage");
+        self.state.garbage_queued.drain(..ready);
+        ready as u32
+    }
+
+    fn tick_garbage(&mut self) {
+        for line in self.state.garbage_queued.iter_mut() {
+            line.delay = line.delay.saturating_sub(1);
+        }
     }
 }
 
+const GARBAGE_SEED_SALT: u64 = 0x9e37_79b9_7f4a_7c15;
+
 pub mod score {
     pub const SINGLE: u32 = 0;
     pub const DOUBLE: u32 = 1;
@@ -557,12 +861,14 @@ pub mod score {
     pub const SPIN_SINGLE: u32 = 2;
     pub const SPIN_DOUBLE: u32 = 4;
     pub const SPIN_TRIPLE: u32 = 6;
+    pub const MINI_SPIN_SINGLE: u32 = 0;
+    pub const MINI_SPIN_DOUBLE: u32 = 1;
     pub const B2B: u32 = 1;
     pub const MAX_COMBO: usize = 9;
     pub const COMBO: [u32; 1 + MAX_COMBO] = [0, 0, 1, 1, 1, 2, 2, 3, 3, 4];
 }
 
-fn calculate_score(cleared: i8, is_immobile: bool, b2b: &mut bool, combo: &mut u32) -> u32 {
+fn calculate_score(cleared: i8, spin: Spin, b2b: &mut bool, combo: &mut u32) -> u32 {
     if cleared == 0 {
         *combo = 0;
         return 0;
@@ -571,21 +877,33 @@ fn calculate_score(cleared: i8, is_immobile: bool, b2b: &mut bool, combo: &mut u
     let mut score;
     let b2b_clear;
 
-    if is_immobile {
-        match cleared {
-            1 => score = score::SPIN_SINGLE,
-            2 => score = score::SPIN_DOUBLE,
-            /* 3, 4 */ _ => score = score::SPIN_TRIPLE,
+    match spin {
+        Spin::Full => {
+            match cleared {
+                1 => score = score::SPIN_SINGLE,
+                2 => score = score::SPIN_DOUBLE,
+                /* 3, 4 */ _ => score = score::SPIN_TRIPLE,
+            }
+            b2b_clear = true;
         }
-        b2b_clear = true;
-    } else {
-        match cleared {
-            1 => score = score::SINGLE,
-            2 => score = score::DOUBLE,
-            3 => score = score::TRIPLE,
-            /* 4 */ _ => score = score::QUAD,
+        Spin::Mini => {
+            match cleared {
+                1 => score = score::MINI_SPIN_SINGLE,
+                2 => score = score::MINI_SPIN_DOUBLE,
+                3 => score = score::TRIPLE,
+                /* 4 */ _ => score = score::QUAD,
+            }
+            b2b_clear = true;
+        }
+        Spin::None => {
+            match cleared {
+                1 => score = score::SINGLE,
+                2 => score = score::DOUBLE,
+                3 => score = score::TRIPLE,
+                /* 4 */ _ => score = score::QUAD,
+            }
+            b2b_clear = cleared >= 4;
         }
-        b2b_clear = cleared >= 4;
     }
 
     if b2b_clear && *b2b {
@@ -598,3 +916,203 @@ fn calculate_score(cleared: i8, is_immobile: bool, b2b: &mut bool, combo: &mut u
 
     score
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn drop_without_clearing(game: &mut Game) {
+        // hard drop at spawn; only valid on a (nearly) empty board
+        game.perform_commands(&[]);
+    }
+
+    #[test]
+    fn test_garbage_waits_for_delay() {
+        let mut game = Game::new_seeded(1);
+        game.queue_garbage(3);
+        assert_eq!(game.garbage_queued.len(), 3);
+        drop_without_clearing(&mut game);
+        assert_eq!(game.garbage_queued.len(), 3);
+        assert!(game.garbage_queued.iter().all(|line| line.delay == 0));
+        drop_without_clearing(&mut game);
+        assert!(game.garbage_queued.is_empty());
+        let garbage_rows = game.board.rows().iter().take(3);
+        for row in garbage_rows {
+            assert_eq!(row.iter().filter(|b| b.is_none()).count(), 1, "{row:?}");
+            assert!(row.iter().flatten().all(|&b| b == NonEmptyBlock::G));
+        }
+    }
+
+    #[test]
+    fn test_garbage_clean_hole() {
+        let mut game = Game::new_seeded(2).with_garbage_rules(GarbageRules {
+            delay: 0,
+            ..GarbageRules::default()
+        });
+        game.queue_garbage(8);
+        drop_without_clearing(&mut game);
+        let holes = (0..8)
+            .map(|y| game.board.rows()[y].iter().position(|b| b.is_none()))
+            .collect::<Vec<_>>();
+        assert!(
+            holes.iter().all(|&h| h.is_some() && h == holes[0]),
+            "{holes:?}"
+        );
+    }
+
+    #[test]
+    fn test_garbage_messy_hole() {
+        let mut game = Game::new_seeded(3).with_garbage_rules(GarbageRules {
+            delay: 0,
+            initial_messiness: 1.0,
+            final_messiness: 1.0,
+            ..GarbageRules::default()
+        });
+        game.queue_garbage(8);
+        drop_without_clearing(&mut game);
+        let holes = (0..8)
+            .map(|y| game.board.rows()[y].iter().position(|b| b.is_none()))
+            .collect::<Vec<_>>();
+        assert!(holes.windows(2).all(|w| w[0] != w[1]), "{holes:?}");
+    }
+
+    #[test]
+    fn test_garbage_messiness_interpolation() {
+        let rules = GarbageRules {
+            initial_messiness: 0.2,
+            final_messiness: 0.6,
+            start_margin: 10.0,
+            end_margin: 30.0,
+            ..GarbageRules::default()
+        };
+        assert_eq!(rules.messiness(0.0), 0.2);
+        assert_eq!(rules.messiness(10.0), 0.2);
+        assert!((rules.messiness(20.0) - 0.4).abs() < 1e-6);
+        assert_eq!(rules.messiness(30.0), 0.6);
+        assert_eq!(rules.messiness(100.0), 0.6);
+    }
+
+    #[test]
+    fn test_move_outcome() {
+        let mut game = Game::new_seeded(5);
+        game.queue_garbage(2);
+        // fill the bottom two rows, except for where an I piece can clear them
+        for y in 0..2 {
+            for x in 0..10 {
+                if x != 9 {
+                    game.state.board[(x, y)] = Some(NonEmptyBlock::G);
+                }
+            }
+        }
+        game.state.current = PieceData::spawn(Piece::I);
+        let outcome = game.perform_commands(&[Command::RotateCw, Command::SonicRight]);
+        assert_eq!(outcome.piece, Piece::I);
+        assert_eq!(outcome.cleared, 2);
+        assert_eq!(outcome.spin, Spin::None);
+        assert_eq!(outcome.score, score::DOUBLE);
+        assert_eq!((outcome.cancelled, outcome.sent), (1, 0));
+        assert_eq!(outcome.tanked, 0);
+        assert_eq!(game.garbage_queued.len(), 1);
+        assert!(outcome.blocked.is_empty());
+        assert!(!outcome.dead);
+    }
+
+    #[test]
+    fn test_move_outcome_blocked() {
+        let mut game = Game::new_seeded(6);
+        let outcome = game.perform_commands(&[Command::SonicLeft, Command::MoveLeft]);
+        assert_eq!(outcome.blocked, [Command::MoveLeft]);
+        assert_eq!(outcome.cleared, 0);
+        assert_eq!(outcome.combo, 0);
+    }
+
+    // rows are given from top to bottom, like they are displayed
+    fn board(rows: &[&str]) -> Board {
+        let mut board = Board::new();
+        for (y, row) in rows.iter().rev().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if c == 'x' {
+                    board[(x as i8, y as i8)] = Some(NonEmptyBlock::G);
+                }
+            }
+        }
+        board
+    }
+
+    #[test]
+    fn test_spin_rules() {
+        let tsd = board(&[
+            "...x......", //
+            "xxxx......",
+            "xxx...xxxx",
+            "xxxx.xxxxx",
+        ]);
+        for rules in [SpinRules::AllSpin, SpinRules::TSpin, SpinRules::AllMini] {
+            let mut game = Game::new_seeded(7).with_spin_rules(rules);
+            game.state.board = tsd.clone();
+            game.state.current = PieceData::spawn(Piece::T);
+            let cmds = [Command::RotateCw, Command::SonicDrop, Command::RotateCw];
+            let outcome = game.perform_commands(&cmds);
+            assert!(outcome.blocked.is_empty());
+            assert_eq!(
+                (outcome.cleared, outcome.spin),
+                (2, Spin::Full),
+                "{rules:?}"
+            );
+            assert_eq!(outcome.score, score::SPIN_DOUBLE);
+        }
+
+        // T pointing up against the floor, with one of the top corners filled
+        let mini = board(&[
+            "x.........", //
+            "..........",
+        ]);
+        let t = PieceData {
+            piece: Piece::T,
+            rotation: Rotation::North,
+            x: 0,
+            y: 1,
+        };
+        let (east, west) = (Rotation::East, Rotation::West);
+        assert_eq!(
+            mini.check_spin(t, SpinRules::TSpin, Some((east, 0))),
+            Spin::Mini
+        );
+        assert_eq!(
+            mini.check_spin(t, SpinRules::TSpin, Some((east, 4))),
+            Spin::Full
+        );
+        assert_eq!(
+            mini.check_spin(t, SpinRules::TSpin, Some((west, 4))),
+            Spin::Full
+        );
+        assert_eq!(mini.check_spin(t, SpinRules::TSpin, None), Spin::None);
+        assert_eq!(
+            mini.check_spin(t, SpinRules::AllSpin, Some((east, 0))),
+            Spin::None
+        );
+
+        let (mut b2b, mut combo) = (false, 0);
+        let score = calculate_score(1, Spin::Mini, &mut b2b, &mut combo);
+        assert_eq!(score, score::MINI_SPIN_SINGLE);
+        assert!(b2b);
+    }
+
+    #[test]
+    #[should_panic(expected = "garbage hole out of range")]
+    fn test_garbage_hole_range() {
+        Board::new().insert_garbage(10);
+    }
+
+    #[test]
+    fn test_garbage_cancel() {
+        let mut game = Game::new_seeded(4);
+        game.queue_garbage(5);
+        let (cancel, attack) = game.cancel_garbage(3);
+        assert_eq!((cancel, attack), (3, 0));
+        assert_eq!(game.garbage_queued.len(), 2);
+        let (cancel, attack) = game.cancel_garbage(4);
+        assert_eq!((cancel, attack), (2, 2));
+        assert!(game.garbage_queued.is_empty());
+    }
+}
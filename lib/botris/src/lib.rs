@@ -10,3 +10,12 @@ pub use api::*;
 
 pub mod game;
 pub use game::*;
+
+pub mod bot;
+pub use bot::Bot;
+
+#[cfg(feature = "mino")]
+pub mod convert;
+
+pub mod versus;
+pub use versus::Versus;
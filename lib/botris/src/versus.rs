@@ -0,0 +1,434 @@
+//! Local simulation of versus matches, for testing bots offline.
+
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use crate::api::RoomData;
+use crate::bot::Bot;
+use crate::game::{Game, GameState, GarbageRules, MoveOutcome, SpinRules};
+
+/// Settings for a versus match.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VersusRules {
+    /// Number of round wins needed to win the match ("first to").
+    pub ft: u32,
+    /// Number of moves each player makes per second of simulated time. Must be positive.
+    pub pps: f32,
+    /// Rules for queueing and tanking garbage.
+    pub garbage: GarbageRules,
+    /// Rules deciding which placements are spins.
+    pub spin: SpinRules,
+    /// A round is called a draw if it lasts longer than this many moves per player.
+    pub max_pieces: u32,
+    /// A match ends without a winner after this many rounds, e.g. if they keep ending
+    /// in draws.
+    pub max_rounds: u32,
+}
+
+impl Default for VersusRules {
+    fn default() -> Self {
+        Self {
+            ft: 1,
+            pps: 2.0,
+            garbage: GarbageRules::default(),
+            spin: SpinRules::default(),
+            max_pieces: 1000,
+            max_rounds: 100,
+        }
+    }
+}
+
+/// A room pps that is not positive is ignored, keeping the default.
+impl From<&RoomData> for VersusRules {
+    fn from(room_data: &RoomData) -> Self {
+        let mut rules = Self {
+            ft: room_data.ft,
+            garbage: room_data.into(),
+            ..Self::default()
+        };
+        if valid_pps(room_data.pps) {
+            rules.pps = room_data.pps;
+        } else {
+            warn!(pps = room_data.pps, "ignoring invalid pps");
+        }
+        rules
+    }
+}
+
+fn valid_pps(pps: f32) -> bool {
+    !pps.is_nan() && pps > 0.0
+}
+
+/// Result of a single round.
+#[derive(Debug, Clone)]
+pub struct RoundResult {
+    /// Index of the winning player, or `None` if the round was a draw.
+    pub winner: Option<usize>,
+    /// Number of moves made by each player in the round.
+    pub moves: u32,
+    /// Simulated duration of the round, in seconds.
+    pub duration: f32,
+    /// Final game states of each player.
+    pub games: Vec<GameState>,
+}
+
+/// Result of an entire match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchResult {
+    /// Index of the player that won the match, or `None` if nobody won `ft` rounds
+    /// within `max_rounds`.
+    pub winner: Option<usize>,
+    /// Number of rounds won by each player.
+    pub wins: Vec<u32>,
+    /// Number of rounds played, incl. draws.
+    pub rounds: u32,
+}
+
+/// Steps several [`Game`]s in lockstep, routing the attack sent by each player into the
+/// garbage queue of an opponent. A bot that gives up (returns `None`) counts as topping
+/// out.
+pub struct Versus {
+    rules: VersusRules,
+    rng: SmallRng,
+    wins: Vec<u32>,
+    rounds: u32,
+}
+
+impl Versus {
+    /// Create a new match between `n_players` players. The piece sequence of each round
+    /// is determined by `seed`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are less than two players, or if `rules.pps` is not positive.
+    pub fn new(n_players: usize, rules: VersusRules, seed: u64) -> Self {
+        assert!(n_players >= 2, "versus requires at least two players");
+        assert!(valid_pps(rules.pps), "invalid pps: {}", rules.pps);
+        Self {
+            rules,
+            rng: SmallRng::seed_from_u64(seed),
+            wins: vec![0; n_players],
+            rounds: 0,
+        }
+    }
+
+    pub fn rules(&self) -> &VersusRules {
+        &self.rules
+    }
+
+    /// Number of rounds won by each player so far.
+    pub fn wins(&self) -> &[u32] {
+        &self.wins
+    }
+
+    /// Returns the winner of the match, if any player has reached `ft` wins.
+    pub fn winner(&self) -> Option<usize> {
+        self.wins.iter().position(|&w| w >= self.rules.ft)
+    }
+
+    /// Play a single round. Every player is given the same piece sequence.
+    pub fn play_round(&mut self, players: &mut [&mut dyn Bot]) -> RoundResult {
+        assert_eq!(players.len(), self.wins.len(), "wrong number of players");
+
+        let seed = self.rng.gen();
+        let mut games = (0..players.len())
+            .map(|_| {
+                Game::new_seeded(seed)
+                    .with_garbage_rules(self.rules.garbage)
+                    .with_spin_rules(self.rules.spin)
+            })
+            .collect::<Vec<_>>();
+
+        for player in players.iter_mut() {
+            player.new_round();
+        }
+
+        let mut moves = 0;
+        let mut elapsed = 0.0;
+
+        let winner = loop {
+            let alive = games.iter().filter(|g| !g.dead).count();
+            if alive <= 1 {
+                break games.iter().position(|g| !g.dead);
+            }
+            if moves >= self.rules.max_pieces {
+                break None;
+            }
+
+            elapsed = moves as f32 / self.rules.pps;
+            for i in 0..games.len() {
+                if games[i].dead {
+                    continue;
+                }
+                games[i].set_elapsed(elapsed);
+                let outcome = match players[i].request_move(&games[i]) {
+                    Some(cmds) => games[i].perform_commands(&cmds),
+                    None => {
+                        warn!(player = i, "gave up");
+                        games[i].state.dead = true;
+                        continue;
+                    }
+                };
+                if let Some(to) = self.send_attack(&mut games, i, &outcome) {
+                    players[to].garbage_received(outcome.sent, &games[to]);
+                }
+                let id = i.to_string();
+                for (j, player) in players.iter_mut().enumerate() {
+                    if j != i {
+                        player.opponent_update(&id, &games[i]);
+                    }
+                }
+            }
+
+            moves += 1;
+        };
+
+        self.rounds += 1;
+        if let Some(i) = winner {
+            self.wins[i] += 1;
+        }
+        debug!(?winner, moves, wins = ?self.wins, "round over");
+
+        RoundResult {
+            winner,
+            moves,
+            duration: elapsed,
+            games: games.into_iter().map(|g| g.state).collect(),
+        }
+    }
+
+    /// Play rounds until a player has won `ft` rounds, or `max_rounds` rounds have been
+    /// played.
+    pub fn play_match(&mut self, players: &mut [&mut dyn Bot]) -> MatchResult {
+        let winner = loop {
+            if let Some(winner) = self.winner() {
+                break Some(winner);
+            }
+            if self.rounds >= self.rules.max_rounds {
+                break None;
+            }
+            self.play_round(players);
+        };
+
+        MatchResult {
+            winner,
+            wins: self.wins.clone(),
+            rounds: self.rounds,
+        }
+    }
+
+    // returns the index of the player that received the attack, if any
+    fn send_attack(
+        &mut self,
+        games: &mut [Game],
+        from: usize,
+        outcome: &MoveOutcome,
+    ) -> Option<usize> {
+        if outcome.sent == 0 {
+            return None;
+        }
+        let targets = (0..games.len())
+            .filter(|&j| j != from && !games[j].dead)
+            .collect::<Vec<_>>();
+        if targets.is_empty() {
+            return None;
+        }
+        let to = targets[self.rng.gen_range(0..targets.len())];
+        trace!(from, to, lines = outcome.sent, "attack");
+        games[to].queue_garbage(outcome.sent);
+        Some(to)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::Command;
+
+    // hard drops at spawn, so it tops out quickly
+    fn stacker(_: &GameState) -> Option<Vec<Command>> {
+        Some(vec![])
+    }
+
+    // alternates between the left and right walls, so it survives a bit longer
+    fn spreader(gs: &GameState) -> Option<Vec<Command>> {
+        if gs.pieces_placed.is_multiple_of(2) {
+            Some(vec![Command::SonicLeft])
+        } else {
+            Some(vec![Command::SonicRight])
+        }
+    }
+
+    // places the piece wherever it leaves the fewest holes and the lowest stack, so it
+    // clears lines and sends some attack through combos
+    fn clearer(gs: &GameState) -> Option<Vec<Command>> {
+        let mut best: Option<((usize, i8), Vec<Command>)> = None;
+        for turns in 0..4 {
+            for dx in -5i8..=5 {
+                let mut pd = gs.current;
+                let mut cmds = vec![Command::RotateCw; turns];
+                for _ in 0..turns {
+                    pd.try_rotate_cw(&gs.board);
+                }
+                let (step, cmd) = match dx < 0 {
+                    true => (-1, Command::MoveLeft),
+                    false => (1, Command::MoveRight),
+                };
+                for _ in 0..dx.abs() {
+                    if pd.try_offset((step, 0), &gs.board) {
+                        cmds.push(cmd);
+                    }
+                }
+                pd.sonic_drop(&gs.board);
+                let mut board = gs.board.clone();
+                board.place_piece(pd);
+                board.clear_lines();
+                let holes = (0..10)
+                    .map(|x| {
+                        let column = (0..board.len()).map(|y| board[(x, y)].is_some());
+                        let top = column.clone().rposition(|filled| filled).unwrap_or(0);
+                        column.take(top).filter(|&filled| !filled).count()
+                    })
+                    .sum::<usize>();
+                let key = (holes, board.len());
+                if best.as_ref().is_none_or(|(k, _)| key < *k) {
+                    best = Some((key, cmds));
+                }
+            }
+        }
+        best.map(|(_, cmds)| cmds)
+    }
+
+    #[test]
+    fn test_versus_round() {
+        let mut vs = Versus::new(2, VersusRules::default(), 0);
+        let result = vs.play_round(&mut [&mut stacker, &mut spreader]);
+        assert_eq!(result.winner, Some(1));
+        assert!(result.games[0].dead);
+        assert!(!result.games[1].dead);
+        assert_eq!(vs.wins(), [0, 1]);
+    }
+
+    #[test]
+    fn test_versus_match() {
+        let rules = VersusRules {
+            ft: 3,
+            ..VersusRules::default()
+        };
+        let mut vs = Versus::new(2, rules, 0);
+        let result = vs.play_match(&mut [&mut spreader, &mut stacker]);
+        assert_eq!(result.winner, Some(0));
+        assert_eq!(result.wins, [3, 0]);
+        assert_eq!(result.rounds, 3);
+    }
+
+    #[test]
+    fn test_versus_match_draws() {
+        let rules = VersusRules {
+            max_pieces: 3,
+            max_rounds: 4,
+            ..VersusRules::default()
+        };
+        let mut vs = Versus::new(2, rules, 0);
+        let result = vs.play_match(&mut [&mut spreader, &mut spreader]);
+        assert_eq!(result.winner, None);
+        assert_eq!(result.wins, [0, 0]);
+        assert_eq!(result.rounds, 4);
+    }
+
+    #[test]
+    fn test_versus_give_up() {
+        let mut vs = Versus::new(3, VersusRules::default(), 0);
+        let result = vs.play_round(&mut [&mut spreader, &mut |_: &GameState| None, &mut stacker]);
+        assert_eq!(result.winner, Some(0));
+        assert_eq!(result.games[1].pieces_placed, 0);
+    }
+
+    #[derive(Default)]
+    struct Hooks {
+        rounds: u32,
+        garbage: u32,
+        updates: u32,
+    }
+
+    impl Bot for Hooks {
+        fn request_move(&mut self, gs: &GameState) -> Option<Vec<Command>> {
+            spreader(gs)
+        }
+
+        fn new_round(&mut self) {
+            self.rounds += 1;
+        }
+
+        fn garbage_received(&mut self, lines: u32, gs: &GameState) {
+            assert!(gs.garbage_queued.len() >= lines as usize);
+            self.garbage += lines;
+        }
+
+        fn opponent_update(&mut self, _opponent: &str, _gs: &GameState) {
+            self.updates += 1;
+        }
+    }
+
+    #[test]
+    fn test_versus_hooks() {
+        let rules = VersusRules {
+            max_pieces: 200,
+            ..VersusRules::default()
+        };
+        let mut vs = Versus::new(2, rules, 0);
+        let mut hooks = Hooks::default();
+        let result = vs.play_round(&mut [&mut hooks, &mut clearer]);
+        assert_eq!(hooks.rounds, 1);
+        assert_eq!(hooks.updates, result.games[1].pieces_placed);
+        // the spreader never clears lines, so it doesn't cancel any of the attack
+        assert!(hooks.garbage > 0, "{result:?}");
+        assert_eq!(hooks.garbage, result.games[1].score);
+    }
+
+    #[test]
+    fn test_versus_draw() {
+        let rules = VersusRules {
+            max_pieces: 3,
+            ..VersusRules::default()
+        };
+        let mut vs = Versus::new(2, rules, 0);
+        let result = vs.play_round(&mut [&mut spreader, &mut spreader]);
+        assert_eq!(result.winner, None);
+        assert_eq!(result.moves, 3);
+        assert_eq!(vs.wins(), [0, 0]);
+    }
+    #[test]
+    fn test_room_pps() {
+        let mut room_data = RoomData {
+            id: String::new(),
+            ft: 2,
+            pps: 3.0,
+            initial_messiness: 0.0,
+            final_messiness: 0.0,
+            start_margin: 0.0,
+            end_margin: 0.0,
+            max_players: 2,
+            game_ongoing: false,
+            players: Vec::new(),
+        };
+        assert_eq!(VersusRules::from(&room_data).pps, 3.0);
+        for pps in [0.0, -1.0, f32::NAN] {
+            room_data.pps = pps;
+            assert_eq!(
+                VersusRules::from(&room_data).pps,
+                VersusRules::default().pps
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid pps")]
+    fn test_invalid_pps() {
+        let rules = VersusRules {
+            pps: 0.0,
+            ..VersusRules::default()
+        };
+        Versus::new(2, rules, 0);
+    }
+}
@@ -10,6 +10,8 @@ pub enum Input {
     Ccw = 3,
     // Drop = 4,
     SonicDrop = 5,
+    /// 180 degree rotation, only available in rule sets that have kicks for it.
+    Half = 6,
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
@@ -72,9 +74,25 @@ impl From<Rot> for u8 {
 #[repr(i8)]
 pub enum Turn {
     Cw = 1,
+    /// 180 degree rotation.
+    Half = 2,
     Ccw = 3,
 }
 
+impl Turn {
+    /// Every turn, in the order they are tried when searching for placements.
+    pub const ALL: [Turn; 3] = [Turn::Cw, Turn::Ccw, Turn::Half];
+
+    /// Returns the turn that undoes this one.
+    pub fn inverse(self) -> Turn {
+        match self {
+            Turn::Cw => Turn::Ccw,
+            Turn::Ccw => Turn::Cw,
+            Turn::Half => Turn::Half,
+        }
+    }
+}
+
 impl ops::Add<Turn> for Rot {
     type Output = Rot;
     fn add(self, t: Turn) -> Self::Output {
@@ -87,6 +105,7 @@ impl From<Turn> for Input {
         match dr {
             Turn::Cw => Input::Cw,
             Turn::Ccw => Input::Ccw,
+            Turn::Half => Input::Half,
         }
     }
 }
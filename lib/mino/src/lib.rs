@@ -16,13 +16,22 @@ pub mod piece;
 pub use piece::{Cells, Pos};
 
 pub mod queue;
-pub use queue::Queue;
+pub use queue::{BagTracker, Queue};
 
 pub mod places;
-pub use places::{places, reach, Places};
+pub use places::{places, reach, reach_rotation, Places};
+
+pub mod pc;
+
+pub mod spin;
+pub use spin::{Spin, SpinRules};
+
+pub mod tspin;
 
 pub mod standard_rules;
 
+pub mod srs_plus;
+
 #[cfg(test)]
 mod test {
     use core::fmt;
@@ -0,0 +1,272 @@
+//! Perfect clear solver: finds sequences of placements that clear every cell of the
+//! matrix.
+
+use alloc::vec::Vec;
+use core::hash::Hash;
+
+use crate::matrix::{Mat, MatBuf, COLS, EMPTY};
+use crate::piece::{FallingPiece, Shape, Spawn, WallKicks};
+use crate::places::places;
+use crate::queue::Queue;
+
+type HashSet<T> = hashbrown::HashSet<T, core::hash::BuildHasherDefault<ahash::AHasher>>;
+
+const ROW_MASK: u16 = !EMPTY;
+
+/// Returns a sequence of placements of the pieces in `queue` (using hold as needed) that
+/// clears the whole matrix, without the stack ever being taller than `max_height`. Tries
+/// the lowest possible height first. Returns `None` if there is no such sequence, or if
+/// the matrix is already empty.
+///
+/// Before searching each position, `stop` is called with the number of positions searched
+/// so far; once it returns `true` the search gives up and returns `None`.
+///
+/// Positions that are known to fail are memoized, and placements are pruned if they
+/// leave an empty region whose size is not a multiple of 4, since it could never be
+/// filled.
+pub fn perfect_clear<T>(
+    matrix: &Mat,
+    queue: Queue<'_, T>,
+    max_height: i8,
+    stop: impl FnMut(usize) -> bool,
+) -> Option<Vec<FallingPiece<T>>>
+where
+    T: Shape + Spawn + WallKicks + Copy + Eq + Hash,
+{
+    if matrix.len() == 0 {
+        return None;
+    }
+    let pieces = queue.next().len() + queue.hold().is_some() as usize;
+    let filled = matrix
+        .rows()
+        .iter()
+        .map(|&row| (row & ROW_MASK).count_ones() as usize)
+        .sum::<usize>();
+
+    // the height is part of the memoized positions, so the solver can be shared
+    let mut solver = Solver {
+        failed: HashSet::default(),
+        path: Vec::new(),
+        nodes: 0,
+        stop,
+        stopped: false,
+    };
+    for height in matrix.len()..=max_height {
+        let empty = height as usize * COLS as usize - filled;
+        if !empty.is_multiple_of(4) || empty / 4 > pieces {
+            continue;
+        }
+        if solver.solve(matrix, queue, height) {
+            return Some(solver.path);
+        }
+        if solver.stopped {
+            break;
+        }
+    }
+    None
+}
+
+struct Solver<T, F> {
+    // positions (matrix, hold, number of next pieces, height) with no solution
+    failed: HashSet<(Vec<u16>, Option<T>, usize, i8)>,
+    path: Vec<FallingPiece<T>>,
+    // number of positions searched
+    nodes: usize,
+    stop: F,
+    stopped: bool,
+}
+
+impl<T, F> Solver<T, F>
+where
+    T: Shape + Spawn + WallKicks + Copy + Eq + Hash,
+    F: FnMut(usize) -> bool,
+{
+    fn solve(&mut self, matrix: &Mat, queue: Queue<'_, T>, height: i8) -> bool {
+        let key = (
+            matrix.rows().to_vec(),
+            queue.hold(),
+            queue.next().len(),
+            height,
+        );
+        if self.failed.contains(&key) {
+            return false;
+        }
+        if self.stopped || (self.stop)(self.nodes) {
+            self.stopped = true;
+            return false;
+        }
+        self.nodes += 1;
+
+        let mut new_matrix = MatBuf::new();
+        for (pc, next_queue) in queue.pop() {
+            for pl in places(matrix, pc) {
+                let (_, ys) = pl.cells.extents();
+                if ys.end > height {
+                    continue;
+                }
+                new_matrix.copy_from(matrix);
+                new_matrix.place(pl.cells);
+                let cleared = new_matrix.clear_lines(pl.cells.bottom());
+                let new_height = height - cleared as i8;
+
+                self.path.push(pl.falling_piece);
+                if new_matrix.len() == 0 {
+                    return true;
+                }
+                if fillable(&new_matrix, new_height)
+                    && self.solve(&new_matrix, next_queue, new_height)
+                {
+                    return true;
+                }
+                self.path.pop();
+                if self.stopped {
+                    // not a failure, the position just was not searched completely
+                    return false;
+                }
+            }
+        }
+
+        self.failed.insert(key);
+        false
+    }
+}
+
+/// Returns `true` if every connected region of empty cells below `height` has a
+/// multiple of 4 cells, which is necessary for the matrix to be filled up to `height`
+/// by pieces of 4 cells.
+pub fn fillable(matrix: &Mat, height: i8) -> bool {
+    let mut empty = (0..height)
+        .map(|y| !matrix.get(y) & ROW_MASK)
+        .collect::<Vec<u16>>();
+    let mut stack = Vec::new();
+
+    for y0 in 0..empty.len() {
+        while empty[y0] != 0 {
+            let x0 = empty[y0].trailing_zeros() as i8;
+            empty[y0] &= !(1 << x0);
+            stack.push((x0, y0));
+            let mut size = 0u32;
+            while let Some((x, y)) = stack.pop() {
+                size += 1;
+                let neighbors = [
+                    (x - 1, y as isize),
+                    (x + 1, y as isize),
+                    (x, y as isize - 1),
+                    (x, y as isize + 1),
+                ];
+                for (nx, ny) in neighbors {
+                    let Ok(ny) = usize::try_from(ny) else {
+                        continue;
+                    };
+                    if !(0..COLS).contains(&nx) || ny >= empty.len() {
+                        continue;
+                    }
+                    if empty[ny] & (1 << nx) != 0 {
+                        empty[ny] &= !(1 << nx);
+                        stack.push((nx, ny));
+                    }
+                }
+            }
+            if !size.is_multiple_of(4) {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::standard_rules::Piece::{self, *};
+
+    fn matrix(rows: &[u16]) -> MatBuf {
+        let mut matrix = MatBuf::new();
+        for (y, &row) in rows.iter().enumerate() {
+            matrix.set(y as i8, row);
+        }
+        matrix
+    }
+
+    // places the solution, checking that it clears the matrix and uses the queue
+    fn check(matrix: &Mat, queue: &[Piece], solution: &[FallingPiece<Piece>]) {
+        let mut m = MatBuf::new();
+        m.copy_from(matrix);
+        let mut q = Queue::from(queue);
+        for fp in solution {
+            let (_, next) = q.pop().find(|&(pc, _)| pc == fp.piece).unwrap();
+            q = next;
+            assert!(!fp.cells().collides(&m));
+            m.place(fp.cells());
+            m.clear_lines(0);
+        }
+        assert_eq!(m.len(), 0);
+    }
+
+    #[test]
+    fn test_fillable() {
+        assert!(fillable(Mat::empty(), 2));
+        // a single empty cell in the corner
+        let m = matrix(&[0b1111111110]);
+        assert!(!fillable(&m, 1));
+        assert!(!fillable(&m, 2));
+        // 16 empty cells, split into regions of 6 and 10 cells
+        let m = matrix(&[0b1100001000, 0b0000001000]);
+        assert!(!fillable(&m, 2));
+        let m = matrix(&[0b1100000100, 0b0000000100]);
+        assert!(fillable(&m, 2));
+    }
+
+    #[test]
+    fn test_one_piece() {
+        // the I piece fills the gap
+        let m = matrix(&[0b0000111111]);
+        let solution = perfect_clear(&m, Queue::from(&[I]), 4, |_| false).unwrap();
+        assert_eq!(solution.len(), 1);
+        check(&m, &[I], &solution);
+        // the O piece does not
+        assert!(perfect_clear(&m, Queue::from(&[O]), 4, |_| false).is_none());
+    }
+
+    #[test]
+    fn test_two_lines() {
+        // 2 rows with the right 4 columns empty: O O, or I I, or L J ...
+        let m = matrix(&[0b0000111111, 0b0000111111]);
+        let queue = [T, O, O];
+        let solution = perfect_clear(&m, Queue::from(&queue), 2, |_| false).unwrap();
+        assert_eq!(solution.len(), 2);
+        check(&m, &queue, &solution);
+
+        let queue = [T, S];
+        assert!(perfect_clear(&m, Queue::from(&queue), 2, |_| false).is_none());
+    }
+
+    #[test]
+    fn test_height() {
+        // the O piece fills the corner, so the second row must be filled as well
+        let m = matrix(&[0b0011111111]);
+        let queue = [O, I, I];
+        assert!(perfect_clear(&m, Queue::from(&queue), 1, |_| false).is_none());
+        let solution = perfect_clear(&m, Queue::from(&queue), 2, |_| false).unwrap();
+        assert_eq!(solution.len(), 3);
+        check(&m, &queue, &solution);
+
+        assert!(perfect_clear(Mat::empty(), Queue::from(&queue), 4, |_| false).is_none());
+    }
+
+    #[test]
+    fn test_stop() {
+        let m = matrix(&[0b0011111111]);
+        let queue = [O, I, I];
+        let mut searched = 0;
+        let solution = perfect_clear(&m, Queue::from(&queue), 2, |n| {
+            searched = n;
+            false
+        });
+        assert!(solution.is_some());
+        assert!(searched > 1);
+        let stop_at = searched - 1;
+        assert!(perfect_clear(&m, Queue::from(&queue), 2, |n| n >= stop_at).is_none());
+        assert!(perfect_clear(&m, Queue::from(&queue), 2, |_| true).is_none());
+    }
+}
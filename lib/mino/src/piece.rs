@@ -78,7 +78,8 @@ impl<T: Spawn> FallingPiece<T> {
     }
 }
 
-/// Interface for pieces that have a list of wall kicks.
+/// Interface for pieces that have a list of wall kicks. A turn with no wall kicks is not
+/// allowed, e.g. [`Turn::Half`] in rule sets without 180 degree rotations.
 pub trait WallKicks {
     fn wall_kicks(&self, r: Rot, dr: Turn) -> &'static [(i8, i8)];
 }
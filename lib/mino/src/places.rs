@@ -6,6 +6,7 @@ use core::{cmp, ops};
 use crate::input::{Dir, Input, Turn};
 use crate::matrix::Mat;
 use crate::piece::{Cells, FallingPiece, Pos, Shape, Spawn, WallKicks};
+use crate::spin::Kick;
 
 type HashSet<T> = hashbrown::HashSet<T, core::hash::BuildHasherDefault<ahash::AHasher>>;
 
@@ -86,14 +87,11 @@ impl<T: Shape + Clone + WallKicks> Iterator for Places<'_, T> {
         loop {
             let mut piece = self.pop()?;
 
-            let mut cw = piece.clone();
-            if cw.try_rotate(self.matrix, Turn::Cw).is_some() {
-                self.push(cw.pos);
-            }
-
-            let mut ccw = piece.clone();
-            if ccw.try_rotate(self.matrix, Turn::Ccw).is_some() {
-                self.push(ccw.pos);
+            for dr in Turn::ALL {
+                let mut rotated = piece.clone();
+                if rotated.try_rotate(self.matrix, dr).is_some() {
+                    self.push(rotated.pos);
+                }
             }
 
             let mut left = piece.clone();
@@ -237,6 +235,34 @@ where
         .map(|(_, node)| node.inputs())
 }
 
+/// Like [`reach`], but the last input is a rotation into `target` that uses the wall kick
+/// `kick`, so that the hard drop does not move the piece afterwards. This is the input
+/// sequence that makes the placement count as a spin, with the wall kick found by
+/// [`crate::spin::last_kick`]. Returns `None` if the piece cannot be rotated into `target`
+/// that way from any reachable position.
+pub fn reach_rotation<T>(matrix: &Mat, target: FallingPiece<T>, kick: Kick) -> Option<Vec<Input>>
+where
+    T: Shape + Spawn + WallKicks + Clone,
+{
+    // position that the piece is rotated from to end up at `target` using `kick`
+    let r0 = target.pos.r + kick.turn.inverse();
+    let &(dx, dy) = target.piece.wall_kicks(r0, kick.turn).get(kick.index)?;
+    let from = Pos::from((target.pos.x - dx, target.pos.y - dy, r0));
+    let mut piece = FallingPiece::new(target.piece.clone(), from);
+    if piece.cells().collides(matrix) {
+        return None;
+    }
+    piece.try_rotate(matrix, kick.turn)?;
+    if piece.pos != target.pos {
+        return None;
+    }
+    ShortestPath::new(matrix, target.piece).find_map(|(_, node)| {
+        let mut inputs = (node.pos == from).then(|| node.inputs())?;
+        inputs.push(kick.turn.into());
+        Some(inputs)
+    })
+}
+
 /// Implements Djikstra's Algorithm in order to list all shortest paths to reachable
 /// places on a matrix.
 struct ShortestPath<'m, T: Shape + Clone> {
@@ -274,14 +300,11 @@ impl<T: Shape + WallKicks + Clone> Iterator for ShortestPath<'_, T> {
         let node = self.unvisited.pop()?;
         let piece = FallingPiece::new(self.piece_type.clone(), node.pos);
 
-        let mut cw = piece.clone();
-        if cw.try_rotate(self.matrix, Turn::Cw).is_some() {
-            self.push(&node, Input::Cw, cw.pos);
-        }
-
-        let mut ccw = piece.clone();
-        if ccw.try_rotate(self.matrix, Turn::Ccw).is_some() {
-            self.push(&node, Input::Ccw, ccw.pos);
+        for dr in Turn::ALL {
+            let mut rotated = piece.clone();
+            if rotated.try_rotate(self.matrix, dr).is_some() {
+                self.push(&node, dr.into(), rotated.pos);
+            }
         }
 
         let mut left = piece.clone();
@@ -341,7 +364,7 @@ impl ShortestPathNode {
         let mut n_drop = self.n_drop;
         match input {
             Input::Left | Input::Right => n_shift += 1,
-            Input::Cw | Input::Ccw => n_rotate += 1,
+            Input::Cw | Input::Ccw | Input::Half => n_rotate += 1,
             Input::SonicDrop => n_drop += 1,
         }
         Self(Rc::new(ShortestPathNodeData {
@@ -445,4 +468,34 @@ mod test_reach {
             [Cw, SonicDrop, Cw]
         });
     }
+
+    #[test]
+    fn test_reach_rotation() {
+        let mut mat = MatBuf::new();
+        // 3 x.........
+        // 2 xxxx.x....
+        // 1 xxxx.x...x
+        // 0 xxxxxx.xxx
+        //   0123456789
+        mat.set(0, 0b1110111111);
+        mat.set(1, 0b1000101111);
+        mat.set(2, 0b0000101111);
+        mat.set(3, 0b0000000001);
+        let tgt = FallingPiece::new(standard_rules::T, (5, 2, Rot::E));
+        // the shortest path rotates in the air, and the hard drop moves the piece after it
+        let inputs = reach(&mat, tgt).unwrap();
+        assert_eq!(inputs, {
+            use Input::*;
+            [Right, Right, Cw]
+        });
+        let kick = crate::spin::last_kick(&mat, tgt).unwrap();
+        let inputs = reach_rotation(&mat, tgt, kick).unwrap();
+        assert_eq!(inputs, {
+            use Input::*;
+            [Right, Right, Right, SonicDrop, Cw]
+        });
+        // there are only 5 wall kicks
+        let kick = Kick { index: 5, ..kick };
+        assert_eq!(reach_rotation(&mat, tgt, kick), None);
+    }
 }
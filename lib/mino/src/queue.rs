@@ -1,6 +1,9 @@
 //! Data structures for queue manipulation.
 
+use alloc::vec::Vec;
 use core::{fmt, mem};
+use rand::seq::SliceRandom;
+use rand::Rng;
 
 /// Represents the upcoming pieces in the queue, incl. held piece.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -100,6 +103,156 @@ impl<'a, T: Copy> Iterator for Pop<'a, T> {
     }
 }
 
+/// Tracks the state of a bag randomizer, which deals each piece of a set once, in random
+/// order, before starting over with a new bag.
+///
+/// Pieces are observed one at a time. If observation does not start at the beginning of
+/// a bag, every position within the bag is considered until the observed pieces rule it
+/// out. If no position is left, the sequence is not consistent with a bag randomizer.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BagTracker<T> {
+    bag: Vec<T>,
+    // one for each position within the bag that is consistent with the observed pieces
+    candidates: Vec<Candidate<T>>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Candidate<T> {
+    // pieces of the current bag that were observed
+    seen: Vec<T>,
+    // number of pieces left in the current bag; fewer than `bag.len() - seen.len()` if
+    // some were dealt before observation started
+    left: usize,
+}
+
+impl<T: Copy + Eq> BagTracker<T> {
+    /// Start tracking at an unknown position in the bag. `bag` contains every piece once.
+    pub fn new(bag: &[T]) -> Self {
+        let candidates = (1..=bag.len())
+            .map(|left| Candidate {
+                seen: Vec::new(),
+                left,
+            })
+            .collect();
+        Self {
+            bag: bag.to_vec(),
+            candidates,
+        }
+    }
+
+    /// Start tracking at the beginning of a bag, e.g. at the start of a game.
+    pub fn at_bag_start(bag: &[T]) -> Self {
+        Self {
+            bag: bag.to_vec(),
+            candidates: alloc::vec![Candidate {
+                seen: Vec::new(),
+                left: bag.len(),
+            }],
+        }
+    }
+
+    /// Observe the next piece that was dealt. Returns `false` if the pieces observed so
+    /// far are not consistent with a bag randomizer.
+    pub fn observe(&mut self, piece: T) -> bool {
+        let bag = &self.bag;
+        self.candidates.retain_mut(|c| {
+            if c.left == 0 {
+                c.seen.clear();
+                c.left = bag.len();
+            }
+            if !bag.contains(&piece) || c.seen.contains(&piece) {
+                return false;
+            }
+            c.seen.push(piece);
+            c.left -= 1;
+            true
+        });
+        self.is_consistent()
+    }
+
+    /// Observe several pieces, see [`BagTracker::observe`].
+    pub fn observe_all(&mut self, pieces: impl IntoIterator<Item = T>) -> bool {
+        pieces.into_iter().for_each(|pc| {
+            self.observe(pc);
+        });
+        self.is_consistent()
+    }
+
+    /// Returns `true` unless the observed pieces could not have been dealt by a bag
+    /// randomizer.
+    pub fn is_consistent(&self) -> bool {
+        !self.candidates.is_empty()
+    }
+
+    /// Returns `true` if the position within the bag is known, i.e. how many pieces are
+    /// left in the current bag.
+    pub fn is_known(&self) -> bool {
+        self.candidates.len() == 1
+    }
+
+    /// Returns the pieces that may be dealt next, in the order of the bag.
+    pub fn next_pieces(&self) -> Vec<T> {
+        self.bag
+            .iter()
+            .copied()
+            .filter(|pc| {
+                let mut bag = self.clone();
+                bag.observe(*pc)
+            })
+            .collect()
+    }
+
+    /// Returns every sequence of `n` pieces that may be dealt next.
+    pub fn continuations(&self, n: usize) -> Vec<Vec<T>> {
+        let mut result = Vec::new();
+        let mut prefix = Vec::with_capacity(n);
+        self.continue_from(&mut prefix, n, &mut result);
+        result
+    }
+
+    fn continue_from(&self, prefix: &mut Vec<T>, n: usize, result: &mut Vec<Vec<T>>) {
+        if prefix.len() == n {
+            result.push(prefix.clone());
+            return;
+        }
+        for &pc in &self.bag {
+            let mut bag = self.clone();
+            if bag.observe(pc) {
+                prefix.push(pc);
+                bag.continue_from(prefix, n, result);
+                prefix.pop();
+            }
+        }
+    }
+
+    /// Sample a sequence of `n` pieces that may be dealt next. Each possible position
+    /// within the bag is equally likely. If the observed pieces are not consistent with a
+    /// bag randomizer, every piece is equally likely instead.
+    pub fn sample(&self, rng: &mut impl Rng, n: usize) -> Vec<T> {
+        let Some(c) = self.candidates.choose(rng) else {
+            return (0..n)
+                .filter_map(|_| self.bag.choose(rng).copied())
+                .collect();
+        };
+        let mut pieces = self
+            .bag
+            .iter()
+            .copied()
+            .filter(|pc| !c.seen.contains(pc))
+            .collect::<Vec<_>>();
+        // pieces dealt before observation started are also missing from the bag
+        pieces.shuffle(rng);
+        pieces.truncate(c.left);
+        while pieces.len() < n {
+            let start = pieces.len();
+            pieces.extend_from_slice(&self.bag);
+            pieces[start..].shuffle(rng);
+        }
+        pieces.truncate(n);
+        pieces
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -139,4 +292,60 @@ mod test {
         assert!(qs.next().is_none());
     }
     */
+
+    #[test]
+    fn test_bag_start() {
+        let mut bag = BagTracker::at_bag_start(b"IJLOSTZ");
+        assert!(bag.is_known());
+        assert!(bag.observe_all(*b"TIOLS"));
+        assert_eq!(bag.next_pieces(), b"JZ");
+        assert_eq!(bag.continuations(2), [b"JZ", b"ZJ"]);
+        assert_eq!(bag.continuations(3).len(), 2 * 7);
+        assert!(bag.observe_all(*b"ZJ"));
+        assert_eq!(bag.next_pieces(), b"IJLOSTZ");
+        assert!(!bag.observe_all(*b"TT"));
+        assert!(!bag.is_consistent());
+    }
+
+    #[test]
+    fn test_bag_unknown_position() {
+        let mut bag = BagTracker::new(b"IJLOSTZ");
+        assert!(!bag.is_known());
+        assert_eq!(bag.next_pieces(), b"IJLOSTZ");
+        assert!(bag.observe_all(*b"IJLOSTZ"));
+        assert!(!bag.is_known());
+        // the Zs must be in different bags
+        assert!(bag.observe_all(*b"Z"));
+        assert!(bag.is_known());
+        assert!(bag.observe_all(*b"TSOLJIT"));
+        assert_eq!(bag.next_pieces(), b"IJLOSZ");
+    }
+
+    #[test]
+    fn test_bag_sample() {
+        use rand::rngs::SmallRng;
+        use rand::SeedableRng;
+
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut bag = BagTracker::at_bag_start(b"IJLOSTZ");
+        bag.observe_all(*b"TIOLS");
+        for _ in 0..10 {
+            let sample = bag.sample(&mut rng, 10);
+            assert_eq!(sample.len(), 10);
+            let mut copy = bag.clone();
+            assert!(copy.observe_all(sample));
+        }
+
+        // observation started in the middle of a bag
+        let mut bag = BagTracker::new(b"IJLOSTZ");
+        for _ in 0..10 {
+            let sample = bag.sample(&mut rng, 10);
+            let mut copy = bag.clone();
+            assert!(copy.observe_all(sample));
+        }
+        // two of the same piece may be at the end of a bag and the start of the next
+        assert!(bag.observe_all(*b"TT"));
+        assert!(!bag.observe_all(*b"T"));
+        assert_eq!(bag.sample(&mut rng, 3).len(), 3);
+    }
 }
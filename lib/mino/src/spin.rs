@@ -0,0 +1,234 @@
+//! Rules deciding which placements count as spins, and whether they are minis.
+
+use crate::input::{Rot, Turn};
+use crate::matrix::Mat;
+use crate::piece::{FallingPiece, Shape, WallKicks};
+use crate::standard_rules::{self, Piece};
+
+/// Kind of spin of a placement.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Spin {
+    #[default]
+    None,
+    Mini,
+    Full,
+}
+
+impl Spin {
+    /// Returns `true` for both full and mini spins.
+    pub fn is_spin(self) -> bool {
+        self != Spin::None
+    }
+}
+
+/// Set of rules deciding which placements are spins.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum SpinRules {
+    /// Any piece that is immobile is a full spin.
+    #[default]
+    AllSpin,
+    /// Only T pieces can spin, following the 3-corner rule: the last move must be a
+    /// rotation, and 3 of the 4 corners around the center of the T must be filled. It is
+    /// a mini unless both corners on the pointing side are filled, or the rotation used
+    /// the last wall kick.
+    TSpin,
+    /// T pieces follow [`SpinRules::TSpin`], and any other piece that is immobile is a
+    /// mini spin.
+    AllMini,
+}
+
+/// Wall kick used by a rotation: the direction of the turn, and the index of the kick in
+/// the table of wall kicks for that turn.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Kick {
+    pub turn: Turn,
+    pub index: usize,
+}
+
+impl Kick {
+    /// Returns `true` if this is the last wall kick tested by the rotation that ends at
+    /// `piece`.
+    pub fn is_last<T: WallKicks>(self, piece: &FallingPiece<T>) -> bool {
+        let r0 = piece.pos.r + self.turn.inverse();
+        self.index + 1 == piece.piece.wall_kicks(r0, self.turn).len()
+    }
+}
+
+impl SpinRules {
+    /// Returns the spin of placing `piece` on `matrix`. `last_kick` is the wall kick used
+    /// by the last rotation, or `None` if the last move was not a rotation.
+    pub fn spin(
+        self,
+        matrix: &Mat,
+        piece: standard_rules::FallingPiece,
+        last_kick: Option<Kick>,
+    ) -> Spin {
+        let immobile = || piece.cells().immobile(matrix);
+        match self {
+            SpinRules::AllSpin if immobile() => Spin::Full,
+            SpinRules::AllSpin => Spin::None,
+            _ if piece.piece == Piece::T => t_spin(matrix, piece, last_kick),
+            SpinRules::AllMini if immobile() => Spin::Mini,
+            _ => Spin::None,
+        }
+    }
+
+    /// Like [`SpinRules::spin`], for placements whose inputs are not known. The last
+    /// move is assumed to be a rotation if the piece can be rotated into place, using
+    /// the last wall kick if possible.
+    pub fn detect(self, matrix: &Mat, piece: standard_rules::FallingPiece) -> Spin {
+        let last_kick = match self.rotates(piece.piece) {
+            true => last_kick(matrix, piece),
+            false => None,
+        };
+        self.spin(matrix, piece, last_kick)
+    }
+
+    /// Returns `true` if a placement of `piece` can only be a spin if the last move is a
+    /// rotation, and the wall kick it used matters.
+    pub fn rotates(self, piece: Piece) -> bool {
+        self != SpinRules::AllSpin && piece == Piece::T
+    }
+}
+
+// 3-corner rule for the T piece
+fn t_spin(matrix: &Mat, piece: standard_rules::FallingPiece, last_kick: Option<Kick>) -> Spin {
+    let Some(kick) = last_kick else {
+        return Spin::None;
+    };
+    let filled =
+        |(x, y): (i8, i8)| !(0..matrix.cols()).contains(&x) || matrix.get(y) & (1 << x) != 0;
+    let (cx, cy) = (piece.pos.x + 1, piece.pos.y - 1);
+    // direction the T points in
+    let (dx, dy) = match piece.pos.r {
+        Rot::N => (0, 1),
+        Rot::E => (1, 0),
+        Rot::S => (0, -1),
+        Rot::W => (-1, 0),
+    };
+    let front = [(cx + dx - dy, cy + dy - dx), (cx + dx + dy, cy + dy + dx)];
+    let back = [(cx - dx - dy, cy - dy - dx), (cx - dx + dy, cy - dy + dx)];
+    let front = front.into_iter().filter(|&c| filled(c)).count();
+    let back = back.into_iter().filter(|&c| filled(c)).count();
+    if front + back < 3 {
+        Spin::None
+    } else if front == 2 || kick.is_last(&piece) {
+        Spin::Full
+    } else {
+        Spin::Mini
+    }
+}
+
+/// Returns the wall kick used by a rotation that ends at `piece`, or `None` if the piece
+/// cannot be rotated into its position. If several rotations can, prefers one that uses
+/// the last wall kick of its table, then the largest index.
+pub fn last_kick<T>(matrix: &Mat, piece: FallingPiece<T>) -> Option<Kick>
+where
+    T: Shape + WallKicks + Clone,
+{
+    kicks(matrix, &piece).max_by_key(|&kick| (kick.is_last(&piece), kick.index))
+}
+
+/// Returns the wall kicks of every rotation that ends at `piece`.
+pub fn kicks<'a, T>(matrix: &'a Mat, piece: &'a FallingPiece<T>) -> impl Iterator<Item = Kick> + 'a
+where
+    T: Shape + WallKicks + Clone,
+{
+    Turn::ALL.into_iter().flat_map(move |turn| {
+        let r0 = piece.pos.r + turn.inverse();
+        let kicks = piece.piece.wall_kicks(r0, turn).iter().enumerate();
+        kicks.filter_map(move |(index, &(dx, dy))| {
+            let mut from = FallingPiece::new(
+                piece.piece.clone(),
+                (piece.pos.x - dx, piece.pos.y - dy, r0),
+            );
+            if from.cells().collides(matrix) || from.try_rotate(matrix, turn).is_none() {
+                return None;
+            }
+            (from.pos == piece.pos).then_some(Kick { turn, index })
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::matrix::MatBuf;
+    use crate::standard_rules::{FallingPiece, Piece::*};
+
+    // rows are given from top to bottom, like they are displayed
+    fn matrix(rows: &[&str]) -> MatBuf {
+        let mut matrix = MatBuf::new();
+        for (y, row) in rows.iter().rev().enumerate() {
+            let bits = row
+                .chars()
+                .enumerate()
+                .filter(|&(_, c)| c == 'x')
+                .fold(0, |bits, (x, _)| bits | (1 << x));
+            matrix.set(y as i8, bits);
+        }
+        matrix
+    }
+
+    #[test]
+    fn test_tsd() {
+        let m = matrix(&[
+            "...x......", //
+            "xxxx......",
+            "xxx...xxxx",
+            "xxxx.xxxxx",
+        ]);
+        // pointing down into the slot
+        let tsd = FallingPiece::new(T, (3, 2, Rot::S));
+        assert!(last_kick(&m, tsd).is_some());
+        for rules in [SpinRules::AllSpin, SpinRules::TSpin, SpinRules::AllMini] {
+            assert_eq!(rules.detect(&m, tsd), Spin::Full, "{rules:?}");
+        }
+        // not a rotation
+        assert_eq!(SpinRules::TSpin.spin(&m, tsd, None), Spin::None);
+    }
+
+    #[test]
+    fn test_mini() {
+        // T pointing up against the floor, with only the bottom corners filled by the
+        // floor and one of the top corners filled
+        let m = matrix(&[
+            "x.........", //
+            "..........",
+        ]);
+        let mini = FallingPiece::new(T, (0, 1, Rot::N));
+        let kick = |turn, index| Some(Kick { turn, index });
+        assert_eq!(
+            SpinRules::TSpin.spin(&m, mini, kick(Turn::Cw, 0)),
+            Spin::Mini
+        );
+        assert_eq!(
+            SpinRules::TSpin.spin(&m, mini, kick(Turn::Cw, 4)),
+            Spin::Full
+        );
+        assert_eq!(
+            SpinRules::TSpin.spin(&m, mini, kick(Turn::Ccw, 4)),
+            Spin::Full
+        );
+        assert_eq!(
+            SpinRules::AllSpin.spin(&m, mini, kick(Turn::Cw, 0)),
+            Spin::None
+        );
+        // 180 degree rotations have no wall kicks in the standard rules
+        assert_eq!(
+            SpinRules::TSpin.spin(&m, mini, kick(Turn::Half, 0)),
+            Spin::Mini
+        );
+
+        // an immobile S piece
+        let m = matrix(&[
+            "xxxxx..xxx", //
+            "xxxx..xxxx",
+        ]);
+        let s = FallingPiece::new(S, (4, 2, Rot::S));
+        assert!(s.cells().immobile(&m));
+        assert_eq!(SpinRules::AllSpin.detect(&m, s), Spin::Full);
+        assert_eq!(SpinRules::TSpin.detect(&m, s), Spin::None);
+        assert_eq!(SpinRules::AllMini.detect(&m, s), Spin::Mini);
+    }
+}
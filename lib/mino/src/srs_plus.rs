@@ -0,0 +1,159 @@
+//! SRS+ rotation system, as used by TETR.IO: the standard pieces and wall kicks, except
+//! that the I piece kicks are symmetric, plus 180 degree rotations with their own kicks.
+
+use core::fmt;
+
+use crate::input::{Rot, Turn};
+use crate::piece::{Cells, Shape, Spawn, WallKicks};
+use crate::standard_rules;
+
+/// Standard piece that is rotated using the SRS+ wall kicks.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Piece(pub standard_rules::Piece);
+
+impl From<standard_rules::Piece> for Piece {
+    fn from(pc: standard_rules::Piece) -> Self {
+        Self(pc)
+    }
+}
+
+impl From<Piece> for standard_rules::Piece {
+    fn from(pc: Piece) -> Self {
+        pc.0
+    }
+}
+
+impl fmt::Display for Piece {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+pub type FallingPiece = super::piece::FallingPiece<Piece>;
+pub type Queue<'a> = super::queue::Queue<'a, Piece>;
+
+impl Spawn for Piece {
+    fn spawn(&self) -> (i8, i8) {
+        self.0.spawn()
+    }
+}
+
+impl Shape for Piece {
+    fn cells(&self, r: Rot) -> Cells {
+        self.0.cells(r)
+    }
+}
+
+static I_WALLKICKS: [[[(i8, i8); 5]; 2]; 4] = [
+    [
+        /* 0-1 */ [(0, 0), (1, 0), (-2, 0), (-2, -1), (1, 2)],
+        /* 0-3 */ [(0, 0), (-1, 0), (2, 0), (2, -1), (-1, 2)],
+    ],
+    [
+        /* 1-2 */ [(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+        /* 1-0 */ [(0, 0), (-1, 0), (2, 0), (-1, -2), (2, 1)],
+    ],
+    [
+        /* 2-3 */ [(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+        /* 2-1 */ [(0, 0), (-2, 0), (1, 0), (-2, 1), (1, -2)],
+    ],
+    [
+        /* 3-0 */ [(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+        /* 3-2 */ [(0, 0), (1, 0), (-2, 0), (1, 2), (-2, -1)],
+    ],
+];
+
+// shared by every piece but O, including I
+static HALF_WALLKICKS: [[(i8, i8); 6]; 4] = [
+    /* 0-2 */ [(0, 0), (0, 1), (1, 1), (-1, 1), (1, 0), (-1, 0)],
+    /* 1-3 */ [(0, 0), (1, 0), (1, 2), (1, 1), (0, 2), (0, 1)],
+    /* 2-0 */ [(0, 0), (0, -1), (-1, -1), (1, -1), (-1, 0), (1, 0)],
+    /* 3-1 */ [(0, 0), (-1, 0), (-1, 2), (-1, 1), (0, 2), (0, 1)],
+];
+
+impl WallKicks for Piece {
+    fn wall_kicks(&self, r: Rot, dr: Turn) -> &'static [(i8, i8)] {
+        let i = r as usize;
+        match (self.0, dr) {
+            // never kicked, in any direction
+            (standard_rules::O, _) => self.0.wall_kicks(r, Turn::Cw),
+            (_, Turn::Half) => &HALF_WALLKICKS[i],
+            (standard_rules::I, Turn::Cw) => &I_WALLKICKS[i][0],
+            (standard_rules::I, Turn::Ccw) => &I_WALLKICKS[i][1],
+            _ => self.0.wall_kicks(r, dr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::input::Input;
+    use crate::matrix::{Mat, MatBuf};
+    use crate::places::{places, reach};
+    use crate::standard_rules::Piece::*;
+    use crate::test::assert_same_set;
+
+    #[test]
+    fn test_wall_kick_lookup() {
+        assert_eq!(Piece(O).wall_kicks(Rot::N, Turn::Half), [(0, 0)]);
+        assert_eq!(
+            Piece(T).wall_kicks(Rot::N, Turn::Half),
+            [(0, 0), (0, 1), (1, 1), (-1, 1), (1, 0), (-1, 0)]
+        );
+        assert_eq!(
+            Piece(Z).wall_kicks(Rot::N, Turn::Cw),
+            Z.wall_kicks(Rot::N, Turn::Cw)
+        );
+        assert_eq!(
+            Piece(I).wall_kicks(Rot::N, Turn::Cw),
+            [(0, 0), (1, 0), (-2, 0), (-2, -1), (1, 2)]
+        );
+    }
+
+    #[test]
+    fn test_half_turn() {
+        let mat = Mat::empty();
+        let mut fp = FallingPiece::new(Piece(T), (3, 1, Rot::N));
+        let cells = fp.try_rotate(mat, Turn::Half).expect("turn(Half)");
+        assert_eq!(fp.cells(), cells);
+        assert_eq!(fp.pos, (3, 2, Rot::S));
+
+        // without 180 degree rotations, the same placement takes two turns
+        let tgt = FallingPiece::new(Piece(T), (3, 2, Rot::S));
+        assert_eq!(reach(mat, tgt), Some([Input::Half].to_vec()));
+        let tgt = standard_rules::FallingPiece::new(T, (3, 2, Rot::S));
+        assert_eq!(reach(mat, tgt), Some([Input::Cw, Input::Cw].to_vec()));
+    }
+
+    #[test]
+    fn test_places() {
+        // 180 degree rotations reach the same placements on an empty matrix
+        for pc in standard_rules::Piece::ALL {
+            assert_same_set(
+                places(Mat::empty(), Piece(pc)).map(|pl| pl.pos),
+                places(Mat::empty(), pc).map(|pl| pl.pos),
+                &pc,
+            );
+        }
+    }
+
+    #[test]
+    fn test_half_spin() {
+        // 2 ..x..xx.x.
+        // 1 xx.....xxx
+        // 0 xxxx...xxx
+        //   0123456789
+        let mut mat = MatBuf::new();
+        mat.set(0, 0b1110001111);
+        mat.set(1, 0b1110000011);
+        mat.set(2, 0b0101100100);
+        let tgt = FallingPiece::new(Piece(T), (4, 1, Rot::N));
+        assert!(places(&mat, Piece(T)).any(|pl| pl.falling_piece == tgt));
+        assert_eq!(reach(&mat, tgt).unwrap().last(), Some(&Input::Half));
+        // only reachable by a 180 degree rotation
+        let tgt = standard_rules::FallingPiece::new(T, (4, 1, Rot::N));
+        assert!(!places(&mat, T).any(|pl| pl.falling_piece == tgt));
+        assert_eq!(reach(&mat, tgt), None);
+    }
+}
@@ -22,6 +22,9 @@ pub use Piece::*;
 static PIECE_NAMES: &[u8] = b"IJLOSTZ";
 
 impl Piece {
+    /// Every piece, i.e. the contents of a bag.
+    pub const ALL: [Piece; 7] = [I, J, L, O, S, T, Z];
+
     pub fn name(self) -> &'static str {
         let i = self as usize;
         core::str::from_utf8(&PIECE_NAMES[i..][..1]).unwrap()
@@ -178,16 +181,22 @@ static I_WALLKICKS: [[[(i8, i8); 5]; 2]; 4] = [
     ],
 ];
 
-// TODO: O_WALLKICKS
+// every rotation of the O piece has the same cells, so it never needs to be kicked
+static O_WALLKICKS: [(i8, i8); 1] = [(0, 0)];
 
+/// There are no 180 degree rotations, so [`Turn::Half`] has no wall kicks.
 impl WallKicks for Piece {
     fn wall_kicks(&self, r: Rot, dr: Turn) -> &'static [(i8, i8)] {
         let i = r as usize;
-        let j = (dr as usize) >> 1; // Cw => 0, Ccw => 1
-        if *self == Piece::I {
-            &I_WALLKICKS[i][j]
-        } else {
-            &WALLKICKS[i][j]
+        let j = match dr {
+            Turn::Cw => 0,
+            Turn::Ccw => 1,
+            Turn::Half => return &[],
+        };
+        match self {
+            Piece::I => &I_WALLKICKS[i][j],
+            Piece::O => &O_WALLKICKS,
+            _ => &WALLKICKS[i][j],
         }
     }
 }
@@ -280,6 +289,8 @@ mod test {
             I.wall_kicks(Rot::E, Turn::Ccw),
             [(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
         );
+        assert_eq!(O.wall_kicks(Rot::W, Turn::Cw), [(0, 0)]);
+        assert!(T.wall_kicks(Rot::N, Turn::Half).is_empty());
     }
 
     #[test]
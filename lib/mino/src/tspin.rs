@@ -0,0 +1,223 @@
+//! Finding T-spin slots: places where a T piece can be spun in to clear lines, before
+//! the T is placed.
+
+use alloc::vec::Vec;
+
+use crate::input::{Input, Rot};
+use crate::matrix::{Mat, MatBuf, FULL};
+use crate::places::{places, reach};
+use crate::standard_rules::{FallingPiece, Piece};
+
+/// Type of T-spin that a slot is set up for.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum SlotKind {
+    /// T-spin single.
+    Single,
+    /// T-spin double, with the T ending pointing down.
+    Double,
+    /// T-spin double with the T ending vertically and clearing its top two rows, e.g. in
+    /// an STSD (super T-spin double) setup.
+    SuperDouble,
+    /// T-spin triple, with the T ending vertically.
+    Triple,
+    /// T-spin double with the T ending vertically and clearing its bottom two rows, like
+    /// a fin T-spin, leaving its top cell behind.
+    Fin,
+    /// T-spin double with the T ending pointing up, like a neo T-spin.
+    Neo,
+}
+
+/// A place where the T piece would be immobile and clear lines.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Slot {
+    pub kind: SlotKind,
+    /// Where the T piece ends up.
+    pub target: FallingPiece,
+    /// Number of lines that would be cleared.
+    pub cleared: u8,
+    /// Shortest input sequence that reaches `target`, see [`reach`].
+    pub inputs: Vec<Input>,
+}
+
+/// Returns the T-spin slots of the matrix that the T piece can reach from its spawn
+/// position. A placement is considered a spin if the piece is immobile, like
+/// [`crate::Cells::immobile`].
+pub fn slots(matrix: &Mat) -> Vec<Slot> {
+    let mut slots = targets(matrix)
+        .into_iter()
+        .filter_map(|(target, cleared)| {
+            let inputs = reach(matrix, target)?;
+            Some(Slot {
+                kind: kind(matrix, target, cleared),
+                target,
+                cleared,
+                inputs,
+            })
+        })
+        .collect::<Vec<_>>();
+    slots.sort_by_key(|slot| (slot.kind, slot.target));
+    slots
+}
+
+/// Returns where the T piece ends up in each of the [`slots`] of the matrix, and the
+/// number of lines it would clear. Cheaper than [`slots`], since the inputs to reach
+/// each slot are not computed.
+pub fn targets(matrix: &Mat) -> Vec<(FallingPiece, u8)> {
+    let mut targets = Vec::new();
+    let mut new_matrix = MatBuf::new();
+    for pl in places(matrix, Piece::T) {
+        if !pl.cells.immobile(matrix) {
+            continue;
+        }
+        new_matrix.copy_from(matrix);
+        new_matrix.place(pl.cells);
+        let cleared = new_matrix.clear_lines(pl.cells.bottom());
+        if cleared > 0 {
+            targets.push((pl.falling_piece, cleared));
+        }
+    }
+    targets
+}
+
+// tells the kinds apart by the final orientation of the T and the rows that it clears,
+// which only depend on the shape of the slot
+fn kind(matrix: &Mat, target: FallingPiece, cleared: u8) -> SlotKind {
+    let mut new_matrix = MatBuf::new();
+    new_matrix.copy_from(matrix);
+    new_matrix.place(target.cells());
+    // the T spans the rows from `cy - 1` to `cy + 1` when vertical, and the row of its
+    // center plus the row it points to when flat
+    let cy = target.pos.y - 1;
+    let full = |y: i8| new_matrix.get(y) == FULL;
+    match (target.pos.r, cleared) {
+        (_, 1) => SlotKind::Single,
+        (Rot::S, _) => SlotKind::Double,
+        (Rot::N, _) => SlotKind::Neo,
+        (Rot::E | Rot::W, 2) if full(cy + 1) => SlotKind::SuperDouble,
+        (Rot::E | Rot::W, 2) => SlotKind::Fin,
+        // a flat T only spans two rows
+        (Rot::E | Rot::W, _) => SlotKind::Triple,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::input::Turn;
+
+    // rows are given from top to bottom, like they are displayed
+    fn matrix(rows: &[&str]) -> MatBuf {
+        let mut matrix = MatBuf::new();
+        for (y, row) in rows.iter().rev().enumerate() {
+            let bits = row
+                .chars()
+                .enumerate()
+                .filter(|&(_, c)| c == 'x')
+                .fold(0, |bits, (x, _)| bits | (1 << x));
+            matrix.set(y as i8, bits);
+        }
+        matrix
+    }
+
+    #[test]
+    fn test_tsd() {
+        let m = matrix(&[
+            "...x......", //
+            "xxxx......",
+            "xxx...xxxx",
+            "xxxx.xxxxx",
+        ]);
+        let slots = slots(&m);
+        let slot = slots.iter().find(|slot| slot.cleared == 2).unwrap();
+        assert_eq!(slot.kind, SlotKind::Double);
+        assert_eq!(slot.cleared, 2);
+        assert_eq!(slot.target.pos.r, Rot::S);
+        let cells = slot.target.cells().coords().collect::<Vec<_>>();
+        assert!(cells.contains(&(4, 0)));
+        assert_eq!(reach(&m, slot.target), Some(slot.inputs.clone()));
+
+        let mut targets = targets(&m);
+        let mut expected = slots
+            .iter()
+            .map(|slot| (slot.target, slot.cleared))
+            .collect::<Vec<_>>();
+        targets.sort();
+        expected.sort();
+        assert_eq!(targets, expected);
+    }
+
+    #[test]
+    fn test_tst() {
+        let m = matrix(&[
+            "xxxx......", //
+            "xxx.......",
+            "xxx.xxxxxx",
+            "xxx..xxxxx",
+            "xxx.xxxxxx",
+        ]);
+        let slots = slots(&m);
+        assert!(
+            slots
+                .iter()
+                .any(|slot| slot.kind == SlotKind::Triple && slot.cleared == 3),
+            "{slots:?}"
+        );
+        assert!(super::slots(&matrix(&["xxxxxxxx.."])).is_empty());
+    }
+
+    #[test]
+    fn test_stsd() {
+        // the T is kicked down into the vertical slot under the overhang
+        let m = matrix(&[
+            ".....x....", //
+            "..........",
+            "xxxxx.xxxx",
+            "xxxxx..xxx",
+            "xxxxx...xx",
+        ]);
+        let slots = slots(&m);
+        let slot = slots.iter().find(|slot| slot.cleared == 2).unwrap();
+        assert_eq!(slot.kind, SlotKind::SuperDouble);
+        assert_eq!(slot.target.pos.r, Rot::E);
+        assert!(slot.target.cells().coords().any(|cell| cell == (5, 0)));
+        assert!(matches!(slot.inputs.last(), Some(Input::Cw | Input::Ccw)));
+    }
+
+    #[test]
+    fn test_fin() {
+        // the T hangs on the block at the top, then is kicked two rows down and leaves its
+        // top cell behind
+        let m = matrix(&[
+            "......x...", //
+            "..........",
+            ".......x..",
+            "xxxxxx..xx",
+            "xxxxxx.xxx",
+        ]);
+        let slots = slots(&m);
+        let slot = slots.iter().find(|slot| slot.cleared == 2).unwrap();
+        assert_eq!(slot.kind, SlotKind::Fin);
+        assert_eq!(slot.target.pos.r, Rot::E);
+        let kick = crate::spin::last_kick(&m, slot.target).unwrap();
+        assert_eq!((kick.turn, kick.index), (Turn::Cw, 4));
+    }
+
+    #[test]
+    fn test_neo() {
+        // the T is dropped into the hole and turned twice to end up pointing up
+        let m = matrix(&[
+            "....x.....", //
+            "..........",
+            "xxxx.xxxxx",
+            "xxx...xxxx",
+            "xxxx..xxx.",
+        ]);
+        let slots = slots(&m);
+        let slot = slots.iter().find(|slot| slot.cleared == 2).unwrap();
+        assert_eq!(slot.kind, SlotKind::Neo);
+        assert_eq!(slot.target.pos.r, Rot::N);
+        assert!(slots
+            .iter()
+            .all(|slot| slot.cleared == 2 || slot.kind == SlotKind::Single));
+    }
+}